@@ -0,0 +1,68 @@
+use openssl::{
+    hash::MessageDigest,
+    pkey::PKey,
+    sign::Signer,
+    error::ErrorStack,
+};
+
+pub const HASH_SIZE: usize = 32;
+
+pub fn hmac(key: &[u8], parts: &[&[u8]]) -> Result<[u8; HASH_SIZE], ErrorStack> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+
+    for part in parts {
+        signer.update(part)?;
+    }
+
+    let mut output = [0u8; HASH_SIZE];
+    signer.sign(&mut output)?;
+
+    Ok(output)
+}
+
+pub fn extract(salt: &[u8], ikm: &[u8]) -> Result<[u8; HASH_SIZE], ErrorStack> {
+    hmac(salt, &[ikm])
+}
+
+pub fn expand(prk: &[u8], info: &[u8], output: &mut [u8]) -> Result<(), ErrorStack> {
+    assert!(output.len() <= 255 * HASH_SIZE);
+
+    let mut previous: Option<[u8; HASH_SIZE]> = None;
+
+    for (counter, chunk) in output.chunks_mut(HASH_SIZE).enumerate() {
+        let block = match previous {
+            Some(previous) => hmac(prk, &[&previous, info, &[counter as u8 + 1]])?,
+            None => hmac(prk, &[info, &[counter as u8 + 1]])?,
+        };
+
+        chunk.copy_from_slice(&block[..chunk.len()]);
+        previous = Some(block);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hkdf::{ extract, expand };
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+
+    // RFC 5869, appendix A.1
+    #[test]
+    fn hkdf_sha256_test_vector() {
+        let ikm = from_hex("0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b");
+        let salt = from_hex("000102030405060708090a0b0c");
+        let info = from_hex("f0f1f2f3f4f5f6f7f8f9");
+
+        let prk = extract(&salt, &ikm).unwrap();
+        assert_eq!(prk.to_vec(), from_hex("077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5"));
+
+        let mut okm = [0u8; 42];
+        expand(&prk, &info, &mut okm).unwrap();
+        assert_eq!(okm.to_vec(), from_hex("3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865"));
+    }
+}
@@ -0,0 +1,15 @@
+use serde::{ Serialize, Deserialize };
+
+// Carried inside `Data::Ack`. Every sequence number below `cumulative` arrived, `ranges` lists
+// the half-open ranges that arrived beyond it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AckPayload {
+    pub cumulative: u64,
+    pub ranges: Vec<(u64, u64)>,
+}
+
+impl AckPayload {
+    pub fn acknowledges(&self, sequence: u64) -> bool {
+        sequence < self.cumulative || self.ranges.iter().any(|(start, end)| (*start..*end).contains(&sequence))
+    }
+}
@@ -0,0 +1,50 @@
+use std::net::SocketAddr;
+
+// Until an address proved that it receives our traffic we send it at most this many times the
+// bytes it sent us, so a spoofed source address cannot be used to aim traffic at someone else
+pub const AMPLIFICATION_FACTOR: u64 = 3;
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct AmplificationLimit {
+    // Traffic to this address is not limited
+    pub validated: Option<SocketAddr>,
+    // The counters belong to this address
+    pub unvalidated: Option<SocketAddr>,
+    pub received_bytes: u64,
+    pub sent_bytes: u64,
+}
+
+impl AmplificationLimit {
+    pub fn received(&mut self, address: SocketAddr, bytes: usize) {
+        if self.validated == Some(address) {
+            return;
+        }
+        if self.unvalidated != Some(address) {
+            self.unvalidated = Some(address);
+            self.received_bytes = 0;
+            self.sent_bytes = 0;
+        }
+
+        self.received_bytes += bytes as u64;
+    }
+
+    pub fn allows(&self, address: SocketAddr, bytes: usize) -> bool {
+        self.validated == Some(address)
+            || (self.unvalidated == Some(address) && self.sent_bytes + bytes as u64 <= AMPLIFICATION_FACTOR * self.received_bytes)
+    }
+
+    pub fn sent(&mut self, address: SocketAddr, bytes: usize) {
+        if self.unvalidated == Some(address) {
+            self.sent_bytes += bytes as u64;
+        }
+    }
+
+    pub fn validate(&mut self, address: SocketAddr) {
+        self.validated = Some(address);
+        if self.unvalidated == Some(address) {
+            self.unvalidated = None;
+            self.received_bytes = 0;
+            self.sent_bytes = 0;
+        }
+    }
+}
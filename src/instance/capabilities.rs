@@ -0,0 +1,46 @@
+use std::fmt::{ Formatter, Debug, Error };
+use serde::{ Serialize, Deserialize };
+
+// Optional features, the value is the bit the feature occupies in `Capabilities`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Capability {
+    ReliableDelivery = 0,
+    Fragmentation = 1,
+    PathMtuDiscovery = 2,
+}
+
+const ALL: [Capability; 3] = [
+    Capability::ReliableDelivery,
+    Capability::Fragmentation,
+    Capability::PathMtuDiscovery,
+];
+
+// Set of features announced in the handshake, bits of features we do not know yet are kept
+// so they simply drop out of the intersection
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Capabilities(u64);
+
+impl Capabilities {
+    // Everything this build implements
+    pub fn all() -> Self {
+        ALL.iter().fold(Capabilities::default(), |capabilities, capability| capabilities.with(*capability))
+    }
+
+    pub fn with(self, capability: Capability) -> Self {
+        Capabilities(self.0 | 1 << capability as u64)
+    }
+
+    pub fn contains(self, capability: Capability) -> bool {
+        self.0 & 1 << capability as u64 != 0
+    }
+
+    pub fn intersection(self, other: Capabilities) -> Self {
+        Capabilities(self.0 & other.0)
+    }
+}
+
+impl Debug for Capabilities {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.debug_set().entries(ALL.iter().filter(|capability| self.contains(**capability))).finish()
+    }
+}
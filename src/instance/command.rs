@@ -1,18 +1,15 @@
-use std::{
-    collections::HashMap,
-    net::SocketAddr,
-};
+use std::net::SocketAddr;
 use crate::{
     x25519::PublicKey,
     SharedMacSecret,
     x25519IDHash,
-    instance::Connection,
+    instance::Delivery,
 };
 
 pub enum Command {
     Exit,
     AddConnection {
-        public_key: PublicKey,
+        public_key: Option<PublicKey>,
         shared_mac_secret: SharedMacSecret,
     },
     ListConnections,
@@ -20,4 +17,9 @@ pub enum Command {
         x25519_id_hash: x25519IDHash,
         endpoint: SocketAddr,
     },
+    SendMessage {
+        x25519_id_hash: x25519IDHash,
+        body: String,
+        delivery: Delivery,
+    },
 }
\ No newline at end of file
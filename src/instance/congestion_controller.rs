@@ -0,0 +1,32 @@
+use std::{
+    fmt::Debug,
+    time::Instant,
+};
+use crate::instance::{ RttEstimator, NewReno, Cubic };
+
+// Decides how many bytes of reliable messages may be unacknowledged at a time
+pub trait CongestionController: Debug + Send {
+    fn window(&self) -> usize;
+    // `sent_at` is when the newest of the acknowledged messages was sent
+    fn on_ack(&mut self, bytes: usize, sent_at: Instant, rtt: &RttEstimator, now: Instant);
+    // A message sent at `sent_at` was lost, reacting once per round trip is up to the controller
+    fn on_congestion_event(&mut self, sent_at: Instant, now: Instant);
+    // Nothing was acknowledged for a whole retransmission timeout
+    fn on_timeout(&mut self, now: Instant);
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum CongestionControl {
+    NewReno,
+    #[default]
+    Cubic,
+}
+
+impl CongestionControl {
+    pub fn controller(self) -> Box<dyn CongestionController> {
+        match self {
+            CongestionControl::NewReno => Box::new(NewReno::default()),
+            CongestionControl::Cubic => Box::new(Cubic::default()),
+        }
+    }
+}
@@ -1,27 +1,219 @@
-use std::net::SocketAddr;
+use std::{
+    net::SocketAddr,
+    time::Instant,
+};
+use rand::thread_rng;
 use crate::{
     x25519IDHash,
-    x25519::PublicKey,
-    instance::EphemeralBlob,
+    SharedMacSecret,
+    x25519::{ PublicKey, EphemeralSecret },
+    noise::HandshakeState,
+    instance::{ Session, Sealed, SessionError, ConnectionStats, PathValidation, Retransmission, FailureReason, DisconnectReason, PathMtu, Capabilities, AmplificationLimit, ObfuscationKey, obfuscation_key, PaddingPolicy, ConnectionSummary, SEALED_PACKET_OVERHEAD, cookie_checker::COOKIE_SIZE },
 };
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug)]
 pub struct Connection {
     pub local_x25519_id_hash: x25519IDHash,
     pub remote_x25519_id_hash: x25519IDHash,
+    pub shared_mac_secret: SharedMacSecret,
     pub endpoint: Option<SocketAddr>,
-    pub state: State
+    pub state: State,
+    pub stats: ConnectionStats,
+    // Timestamp of the last accepted handshake initiation
+    pub latest_initiation_timestamp: u64,
+    pub path_validation: Option<PathValidation>,
+    // Present while a handshake is under way or the session is not confirmed by the peer yet
+    pub retransmission: Option<Retransmission>,
+    // Messages sent while the session is being set up again, delivered once it is established
+    pub queued_messages: Vec<String>,
+    pub path_mtu: PathMtu,
+    // Handed out by the peer while it was busy, attached to our initiations until it expires
+    pub cookie: Option<(Instant, [u8; COOKIE_SIZE])>,
+    pub amplification: AmplificationLimit,
+    // Present if packets to and from the peer are obfuscated
+    pub obfuscation: Option<ObfuscationKey>,
+}
+
+impl Connection {
+    // Nothing happens until the peer is connected to or reaches out first
+    pub fn new(local_x25519_id_hash: x25519IDHash, remote_x25519_id_hash: x25519IDHash, shared_mac_secret: SharedMacSecret, remote_public_key: Option<PublicKey>, obfuscation: Option<ObfuscationKey>) -> Self {
+        Connection {
+            local_x25519_id_hash,
+            remote_x25519_id_hash,
+            shared_mac_secret,
+            endpoint: None,
+            state: State::Pending {
+                remote_public_key,
+                handshake_state: None,
+                local_ratchet_secret: None,
+                remote_ratchet_public_key: None,
+                remote_capabilities: Capabilities::default(),
+                remote_padding_policy: PaddingPolicy::default(),
+            },
+            stats: ConnectionStats::default(),
+            latest_initiation_timestamp: 0,
+            path_validation: None,
+            retransmission: None,
+            queued_messages: Vec::new(),
+            path_mtu: PathMtu::default(),
+            cookie: None,
+            amplification: AmplificationLimit::default(),
+            obfuscation,
+        }
+    }
+
+    // What was learned about the old path does not carry over to a new one
+    pub fn set_endpoint(&mut self, endpoint: SocketAddr) {
+        if self.endpoint != Some(endpoint) {
+            self.path_mtu = PathMtu::default();
+        }
+        self.endpoint = Some(endpoint);
+    }
+
+    // What we put on the wire instead of `local_x25519_id_hash`
+    pub fn local_routing_id(&self) -> x25519IDHash {
+        self.local_x25519_id_hash.routing_id(&self.shared_mac_secret, x25519IDHash::current_epoch())
+    }
+
+    pub fn remote_public_key(&self) -> Option<PublicKey> {
+        match &self.state {
+            State::Pending { remote_public_key, .. } => *remote_public_key,
+            State::Established { session, .. } => Some(session.remote_public_key),
+            State::Failed { remote_public_key, .. } => *remote_public_key,
+            State::Disconnected { remote_public_key, .. } => Some(*remote_public_key),
+        }
+    }
+
+    // Features both peers agreed on, nothing is agreed before the session is established
+    pub fn capabilities(&self) -> Capabilities {
+        match &self.state {
+            State::Established { capabilities, .. } => *capabilities,
+            _ => Capabilities::default(),
+        }
+    }
+
+    pub fn summary(&self) -> ConnectionSummary {
+        let state = match &self.state {
+            State::Pending { handshake_state: None, .. } => "pending".to_string(),
+            State::Pending { .. } => "handshaking".to_string(),
+            State::Established { .. } => "established".to_string(),
+            State::Failed { reason, .. } => format!("failed: {}", reason),
+            State::Disconnected { reason, .. } => format!("disconnected: {}", reason),
+        };
+
+        ConnectionSummary {
+            remote_public_key: self.remote_public_key(),
+            endpoint: self.endpoint,
+            state,
+            stats: self.stats,
+            capabilities: self.capabilities(),
+            padding_policy: self.padding_policy(),
+            path_mtu: self.path_mtu.plpmtu,
+        }
+    }
+
+    // Agreed on in the handshake like the capabilities, nothing is padded before that
+    pub fn padding_policy(&self) -> PaddingPolicy {
+        match &self.state {
+            State::Established { padding_policy, .. } => *padding_policy,
+            _ => PaddingPolicy::None,
+        }
+    }
+
+    // Bytes obfuscation adds to every packet, not counting its random padding
+    pub fn obfuscation_overhead(&self) -> usize {
+        match self.obfuscation {
+            Some(_) => obfuscation_key::OVERHEAD,
+            None => 0,
+        }
+    }
+
+    // Payloads are only padded as far as a datagram on the current path has room for
+    pub fn seal(&mut self, label: &[u8], plaintext: &[u8]) -> Result<Sealed, SessionError> {
+        let limit = self.path_mtu.plpmtu.saturating_sub(SEALED_PACKET_OVERHEAD + self.obfuscation_overhead());
+        let (session, padding_policy) = match &mut self.state {
+            State::Established { session, padding_policy, .. } => (session, *padding_policy),
+            _ => return Err(SessionError::UnknownGeneration),
+        };
+
+        let padded = padding_policy.pad(&mut thread_rng(), plaintext, limit);
+        let sealed = session.seal(label, self.local_x25519_id_hash.as_ref(), &padded)?;
+        self.stats.sent_packets += 1;
+
+        Ok(sealed)
+    }
+
+    // Duplicates and packets that fell out of the replay window are dropped silently. When the
+    // newest packet so far arrives from somewhere else the peer might have moved, path probes
+    // pass no `sender` since they must not start a validation themselves.
+    pub fn open(&mut self, label: &[u8], sealed: &Sealed, sender: Option<SocketAddr>) -> Option<Vec<u8>> {
+        let (session, padding_policy) = match &mut self.state {
+            State::Established { session, padding_policy, .. } => (session, *padding_policy),
+            _ => return None,
+        };
+
+        let newest = session.replay_window.is_newest(sealed.counter);
+        match session.open(label, self.remote_x25519_id_hash.as_ref(), sealed) {
+            Ok(padded) => {
+                let plaintext = match padding_policy.unpad(padded) {
+                    Some(plaintext) => plaintext,
+                    None => {
+                        self.stats.rejected_packets += 1;
+                        println!("Received malformed padding from {}", self.remote_x25519_id_hash);
+                        return None;
+                    },
+                };
+
+                self.stats.received_packets += 1;
+
+                if let (true, Some(sender)) = (newest, sender) {
+                    if self.endpoint == Some(sender) {
+                        self.path_validation = None;
+                    } else if self.path_validation.map(|validation| validation.endpoint) != Some(sender) {
+                        self.path_validation = Some(PathValidation::new(sender));
+                    }
+                }
+
+                Some(plaintext)
+            },
+            Err(SessionError::Duplicate) => {
+                self.stats.duplicate_packets += 1;
+                None
+            },
+            Err(SessionError::TooOld) => {
+                self.stats.too_old_packets += 1;
+                None
+            },
+            Err(error) => {
+                self.stats.rejected_packets += 1;
+                println!("Failed to open packet from {}: {}", self.remote_x25519_id_hash, error);
+                None
+            },
+        }
+    }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug)]
 pub enum State {
     Pending {
-        remote_public_key: PublicKey,
-        local_ephemeral_blob: Option<EphemeralBlob>,
-        remote_ephemeral_blob: Option<EphemeralBlob>,
-        sent_handshake: bool,
+        remote_public_key: Option<PublicKey>,
+        handshake_state: Option<Box<HandshakeState>>,
+        local_ratchet_secret: Option<EphemeralSecret>,
+        remote_ratchet_public_key: Option<PublicKey>,
+        remote_capabilities: Capabilities,
+        remote_padding_policy: PaddingPolicy,
     },
     Established {
-        placeholder: ()
-    }
-}
\ No newline at end of file
+        session: Box<Session>,
+        capabilities: Capabilities,
+        padding_policy: PaddingPolicy,
+    },
+    Failed {
+        remote_public_key: Option<PublicKey>,
+        reason: FailureReason,
+    },
+    Disconnected {
+        remote_public_key: PublicKey,
+        reason: DisconnectReason,
+    },
+}
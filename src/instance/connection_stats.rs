@@ -0,0 +1,17 @@
+// Counts post-handshake packets unless noted otherwise
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ConnectionStats {
+    pub sent_packets: u64,
+    pub received_packets: u64,
+    pub duplicate_packets: u64,
+    pub too_old_packets: u64,
+    pub rejected_packets: u64,
+    // Handshake initiations that were not newer than the last accepted one
+    pub replayed_handshakes: u64,
+    // Fragments that could not be added to a partial message
+    pub dropped_fragments: u64,
+    // Packets of any kind held back by the anti-amplification limit
+    pub amplification_limited_packets: u64,
+    // Packets of any kind the socket failed to send
+    pub send_errors: u64,
+}
@@ -0,0 +1,19 @@
+use std::net::SocketAddr;
+use crate::{
+    x25519::PublicKey,
+    instance::{ ConnectionStats, Capabilities, PaddingPolicy },
+};
+
+// What `Command::ListConnections` reports about a connection, none of its secrets leave the
+// instance thread
+#[derive(Debug, Clone)]
+pub struct ConnectionSummary {
+    pub remote_public_key: Option<PublicKey>,
+    pub endpoint: Option<SocketAddr>,
+    pub state: String,
+    pub stats: ConnectionStats,
+    pub capabilities: Capabilities,
+    pub padding_policy: PaddingPolicy,
+    // Largest datagram known to get through
+    pub path_mtu: usize,
+}
@@ -0,0 +1,91 @@
+use std::{
+    net::SocketAddr,
+    time::{ Duration, Instant },
+};
+use rand::{
+    prelude::ThreadRng,
+    RngCore,
+};
+use openssl::memcmp;
+use crate::{
+    hkdf::{ self, HASH_SIZE },
+    x25519::CryptoError,
+};
+
+pub const COOKIE_SIZE: usize = 16;
+// The secret behind the cookies is replaced this often, older cookies stop working with it
+pub const COOKIE_LIFETIME: Duration = Duration::from_secs(120);
+const LOAD_WINDOW: Duration = Duration::from_secs(1);
+
+// Once more initiations than the threshold needed a DH within a second, initiators first have to
+// prove with a cookie that they receive traffic at their source address
+#[derive(Debug)]
+pub struct CookieChecker {
+    secret: [u8; HASH_SIZE],
+    rotated_at: Instant,
+    busy_threshold: u32,
+    handshakes: u32,
+    window_started_at: Instant,
+}
+
+impl CookieChecker {
+    pub fn new(busy_threshold: u32, rng: &mut ThreadRng) -> Self {
+        let now = Instant::now();
+        let mut secret = [0u8; HASH_SIZE];
+        rng.fill_bytes(&mut secret);
+
+        CookieChecker {
+            secret,
+            rotated_at: now,
+            busy_threshold,
+            handshakes: 0,
+            window_started_at: now,
+        }
+    }
+
+    pub fn cookie(&mut self, sender: SocketAddr, rng: &mut ThreadRng, now: Instant) -> Result<[u8; COOKIE_SIZE], CryptoError> {
+        if now.duration_since(self.rotated_at) >= COOKIE_LIFETIME {
+            rng.fill_bytes(&mut self.secret);
+            self.rotated_at = now;
+        }
+
+        let address = match sender {
+            SocketAddr::V4(address) => address.ip().octets().to_vec(),
+            SocketAddr::V6(address) => address.ip().octets().to_vec(),
+        };
+        let mac = hkdf::hmac(&self.secret, &[&address, &sender.port().to_be_bytes()]).map_err(|_| CryptoError::Mac)?;
+
+        let mut cookie = [0u8; COOKIE_SIZE];
+        cookie.copy_from_slice(&mac[..COOKIE_SIZE]);
+        Ok(cookie)
+    }
+
+    pub fn verify(&mut self, sender: SocketAddr, cookie_mac: &[u8; COOKIE_SIZE], parts: &[&[u8]], rng: &mut ThreadRng, now: Instant) -> Result<bool, CryptoError> {
+        let cookie = self.cookie(sender, rng, now)?;
+
+        Ok(memcmp::eq(cookie_mac, &CookieChecker::mac(&cookie, parts)?))
+    }
+
+    // What an initiator attaches to prove it holds the cookie for its address
+    pub fn mac(cookie: &[u8; COOKIE_SIZE], parts: &[&[u8]]) -> Result<[u8; COOKIE_SIZE], CryptoError> {
+        let mac = hkdf::hmac(cookie, parts).map_err(|_| CryptoError::Mac)?;
+
+        let mut cookie_mac = [0u8; COOKIE_SIZE];
+        cookie_mac.copy_from_slice(&mac[..COOKIE_SIZE]);
+        Ok(cookie_mac)
+    }
+
+    pub fn is_busy(&mut self, now: Instant) -> bool {
+        if now.duration_since(self.window_started_at) >= LOAD_WINDOW {
+            self.handshakes = 0;
+            self.window_started_at = now;
+        }
+
+        self.handshakes >= self.busy_threshold
+    }
+
+    // Counts an initiation that is about to cost us a DH
+    pub fn record_handshake(&mut self) {
+        self.handshakes += 1;
+    }
+}
@@ -0,0 +1,112 @@
+use std::time::Instant;
+use crate::instance::{
+    RttEstimator,
+    CongestionController,
+    path_mtu::BASE_PLPMTU,
+    new_reno::{ INITIAL_WINDOW, MIN_WINDOW },
+};
+
+const C: f64 = 0.4;
+const BETA: f64 = 0.7;
+
+// CUBIC as described in RFC 9438, windows are kept in bytes and converted to segments of the
+// base packet size where the formulas need them
+#[derive(Debug, Copy, Clone)]
+pub struct Cubic {
+    pub window: usize,
+    pub ssthresh: usize,
+    pub recovery_start: Option<Instant>,
+    // Window before the last reduction
+    pub w_max: f64,
+    // Start of the current congestion avoidance stage
+    pub epoch_start: Option<Instant>,
+    pub k: f64,
+    // What Reno would have reached in the same time
+    pub w_est: f64,
+}
+
+impl Default for Cubic {
+    fn default() -> Self {
+        Cubic {
+            window: INITIAL_WINDOW,
+            ssthresh: usize::MAX,
+            recovery_start: None,
+            w_max: 0.0,
+            epoch_start: None,
+            k: 0.0,
+            w_est: 0.0,
+        }
+    }
+}
+
+impl Cubic {
+    fn in_recovery(&self, sent_at: Instant) -> bool {
+        self.recovery_start.is_some_and(|recovery_start| sent_at <= recovery_start)
+    }
+
+    fn segments(bytes: usize) -> f64 {
+        bytes as f64 / BASE_PLPMTU as f64
+    }
+
+    fn reduce(&mut self, now: Instant) {
+        let window = Cubic::segments(self.window);
+
+        // Fast convergence, leave room for flows that joined since the last reduction
+        self.w_max = if window < self.w_max { window * (1.0 + BETA) / 2.0 } else { window };
+        self.ssthresh = ((self.window as f64 * BETA) as usize).max(MIN_WINDOW);
+        self.window = self.ssthresh;
+        self.recovery_start = Some(now);
+        self.epoch_start = None;
+    }
+}
+
+impl CongestionController for Cubic {
+    fn window(&self) -> usize {
+        self.window
+    }
+
+    fn on_ack(&mut self, bytes: usize, sent_at: Instant, rtt: &RttEstimator, now: Instant) {
+        if self.in_recovery(sent_at) {
+            return;
+        }
+
+        if self.window < self.ssthresh {
+            self.window += bytes;
+            return;
+        }
+
+        let window = Cubic::segments(self.window);
+        let epoch_start = match self.epoch_start {
+            Some(epoch_start) => epoch_start,
+            None => {
+                self.k = ((self.w_max - window).max(0.0) / C).cbrt();
+                self.w_est = window;
+                self.epoch_start = Some(now);
+                now
+            },
+        };
+
+        let t = (now - epoch_start + rtt.srtt.unwrap_or_default()).as_secs_f64();
+        let target = (C * (t - self.k).powi(3) + self.w_max).clamp(window, 1.5 * window);
+        let acked = Cubic::segments(bytes);
+        self.w_est += 3.0 * (1.0 - BETA) / (1.0 + BETA) * acked / window;
+
+        let growth = if self.w_est > target {
+            self.w_est - window
+        } else {
+            (target - window) / window * acked
+        };
+        self.window += (growth.max(0.0) * BASE_PLPMTU as f64) as usize;
+    }
+
+    fn on_congestion_event(&mut self, sent_at: Instant, now: Instant) {
+        if !self.in_recovery(sent_at) {
+            self.reduce(now);
+        }
+    }
+
+    fn on_timeout(&mut self, now: Instant) {
+        self.reduce(now);
+        self.window = MIN_WINDOW;
+    }
+}
@@ -0,0 +1,8 @@
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Delivery {
+    // Retransmitted until the peer acknowledges it and handed over in the order it was sent
+    #[default]
+    Reliable,
+    // Sent once and handed over as soon as it arrives
+    Unreliable,
+}
@@ -0,0 +1,18 @@
+use std::fmt::{ Formatter, Display, Error };
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DisconnectReason {
+    IdleTimeout,
+    RekeyFailed,
+    UndecryptableMessage,
+}
+
+impl Display for DisconnectReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            DisconnectReason::IdleTimeout => f.write_str("peer went idle"),
+            DisconnectReason::RekeyFailed => f.write_str("could not derive the next session keys"),
+            DisconnectReason::UndecryptableMessage => f.write_str("could not decrypt a reliable message"),
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use std::time::{ Duration, Instant };
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+// Counts packets dropped before they could be tied to a connection. Anyone can send those, so
+// they are reported at most once per interval instead of once each
+#[derive(Debug, Default)]
+pub struct DropCounter {
+    dropped: u64,
+    reported_at: Option<Instant>,
+}
+
+impl DropCounter {
+    // Returns how many packets were dropped since the last report when another one is due
+    pub fn record(&mut self, now: Instant) -> Option<u64> {
+        self.dropped += 1;
+
+        match self.reported_at {
+            Some(reported_at) if now.duration_since(reported_at) < REPORT_INTERVAL => None,
+            _ => {
+                self.reported_at = Some(now);
+                Some(std::mem::take(&mut self.dropped))
+            },
+        }
+    }
+}
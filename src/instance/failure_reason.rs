@@ -0,0 +1,20 @@
+use std::fmt::{ Formatter, Display, Error };
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FailureReason {
+    HandshakeTimeout,
+    UnsupportedVersion,
+    KeyConfirmationFailed,
+    KeyDerivationFailed,
+}
+
+impl Display for FailureReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            FailureReason::HandshakeTimeout => f.write_str("handshake timed out"),
+            FailureReason::UnsupportedVersion => f.write_str("peer does not support our protocol version"),
+            FailureReason::KeyConfirmationFailed => f.write_str("peer did not derive the same session keys"),
+            FailureReason::KeyDerivationFailed => f.write_str("failed to derive the handshake keys"),
+        }
+    }
+}
@@ -0,0 +1,10 @@
+use serde::{ Serialize, Deserialize };
+
+// Carried inside `Data::Fragment`, a piece of a `MessagePayload` too large for one datagram
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FragmentPayload {
+    pub message_id: u64,
+    pub index: u16,
+    pub count: u16,
+    pub data: Vec<u8>,
+}
@@ -0,0 +1,709 @@
+use std::{
+    net::{ SocketAddr, UdpSocket },
+    time::Instant,
+};
+use rand::{ prelude::ThreadRng, RngCore };
+use openssl::memcmp;
+use crate::{
+    x25519::{ PublicKey, EphemeralSecret, CryptoError },
+    noise::{ HandshakePattern, HandshakeState, NoiseError },
+    ratchet::DoubleRatchet,
+    x25519IDHash,
+    SharedMacSecret,
+    hkdf::HASH_SIZE,
+    instance::{
+        Instance, Connection, connection, Packet, Data, packet, Response, Delivery,
+        Session, SessionKey, SessionKeys, Role, HandshakePayload, Capabilities, PaddingPolicy, PathMtu,
+        CookieChecker, cookie_checker, Retransmission, Retransmit, FailureReason,
+        PROLOGUE, COOKIE_KEY_LABEL,
+    },
+};
+
+impl Instance {
+    fn new_handshake_state(&self, rng: &mut ThreadRng, initiator: bool, remote_public_key: Option<PublicKey>, shared_mac_secret: &SharedMacSecret) -> Result<HandshakeState, NoiseError> {
+        let pattern = match remote_public_key {
+            Some(_) => HandshakePattern::ik().with_psk(2),
+            None => HandshakePattern::xx().with_psk(3),
+        };
+
+        let mut psk = [0u8; 32];
+        psk.copy_from_slice(shared_mac_secret.as_ref());
+
+        HandshakeState::new(
+            pattern,
+            initiator,
+            &[PROLOGUE, &[packet::VERSION]].concat(),
+            Some((self.private_key, self.public_key)),
+            EphemeralSecret::new(rng),
+            if initiator { remote_public_key } else { None },
+            Some(psk)
+        )
+    }
+
+    fn initiate_handshake(&self, socket: &UdpSocket, rng: &mut ThreadRng, connection: &mut Connection, endpoint: SocketAddr) {
+        if let connection::State::Failed { .. } | connection::State::Disconnected { .. } = connection.state {
+            connection.state = connection::State::Pending {
+                remote_public_key: connection.remote_public_key(),
+                handshake_state: None,
+                local_ratchet_secret: None,
+                remote_ratchet_public_key: None,
+                remote_capabilities: Capabilities::default(),
+                remote_padding_policy: PaddingPolicy::default(),
+            };
+        }
+
+        if let connection::State::Pending { remote_public_key, handshake_state, local_ratchet_secret, .. } = &mut connection.state {
+            let ratchet_secret = EphemeralSecret::new(rng);
+            let initiation = self.new_handshake_state(rng, true, *remote_public_key, &connection.shared_mac_secret)
+                .and_then(|mut state| Ok((self.write_handshake_message(&mut state, &ratchet_secret)?, state)));
+            let (message, state) = match initiation {
+                Ok(initiation) => initiation,
+                Err(error) => {
+                    println!("Failed to start handshake with {}: {}", connection.remote_x25519_id_hash, error);
+                    self.fail(connection, FailureReason::KeyDerivationFailed);
+                    return;
+                },
+            };
+            let cookie_mac = match connection.cookie {
+                Some((received_at, cookie)) if received_at.elapsed() < cookie_checker::COOKIE_LIFETIME => {
+                    CookieChecker::mac(&cookie, &[connection.local_x25519_id_hash.as_ref(), &[0], &message])
+                },
+                _ => Ok([0u8; cookie_checker::COOKIE_SIZE]),
+            };
+            let cookie_mac = match cookie_mac {
+                Ok(cookie_mac) => cookie_mac,
+                Err(error) => {
+                    println!("Failed to start handshake with {}: {}", connection.remote_x25519_id_hash, error);
+                    self.fail(connection, FailureReason::KeyDerivationFailed);
+                    return;
+                },
+            };
+            *handshake_state = Some(Box::new(state));
+            *local_ratchet_secret = Some(ratchet_secret);
+
+            // We picked the address ourselves, so it is not limited
+            connection.set_endpoint(endpoint);
+            connection.amplification.validate(endpoint);
+            connection.path_validation = None;
+
+            let data = Packet::new(
+                connection.local_routing_id(),
+                &connection.shared_mac_secret,
+                Data::Handshake {
+                    message_index: 0,
+                    cookie_mac,
+                    message
+                }
+            ).encode();
+            Instance::send_to(socket, connection, &data, endpoint);
+        }
+    }
+
+    pub(super) fn start_handshake(&self, socket: &UdpSocket, rng: &mut ThreadRng, connection: &mut Connection, endpoint: SocketAddr, now: Instant) {
+        self.initiate_handshake(socket, rng, connection, endpoint);
+        connection.retransmission = Some(Retransmission::new(Retransmit::Initiation, now, now, &self.retransmit_policy, rng));
+    }
+
+    pub(super) fn handle_handshake(&self, socket: &UdpSocket, rng: &mut ThreadRng, connection: &mut Connection, sender: SocketAddr, message_index: u8, message: &[u8]) {
+        if message_index == 0 {
+            let remote_public_key = match &connection.state {
+                connection::State::Pending { remote_public_key, handshake_state, .. } => {
+                    if let Some(state) = handshake_state {
+                        // Both peers sent an initiation at the same time, the one with the larger ephemeral key
+                        // stays the initiator and the other one answers as the responder
+                        let simultaneous_open = state.is_initiator() && state.message_index() == 1;
+                        if !simultaneous_open {
+                            println!("Received handshake from {} multiple times!", connection.remote_x25519_id_hash);
+                            return;
+                        }
+                        if message.len() < 32 || state.local_ephemeral_public_key().as_ref() > &message[..32] {
+                            return;
+                        }
+                    }
+
+                    *remote_public_key
+                },
+                // The peer's session ran out or it is trying again after we gave up, answer its new handshake
+                connection::State::Established { .. }
+                | connection::State::Failed { .. }
+                | connection::State::Disconnected { .. } => connection.remote_public_key(),
+            };
+
+            let mut state = match self.new_handshake_state(rng, false, remote_public_key, &connection.shared_mac_secret) {
+                Ok(state) => state,
+                Err(error) => {
+                    println!("Failed to answer handshake from {}: {}", connection.remote_x25519_id_hash, error);
+                    self.fail(connection, FailureReason::KeyDerivationFailed);
+                    return;
+                },
+            };
+            let payload = match state.read_message(message) {
+                Ok(payload) => payload,
+                Err(error) => {
+                    println!("Rejected handshake from {}: {}", connection.remote_x25519_id_hash, error);
+                    return;
+                },
+            };
+            let payload = match bincode::deserialize::<HandshakePayload>(&payload) {
+                Ok(payload) => payload,
+                Err(_) => {
+                    println!("Received malformed handshake payload from {}", connection.remote_x25519_id_hash);
+                    return;
+                },
+            };
+            if let (Some(expected), Some(actual)) = (remote_public_key, state.remote_static_public_key()) {
+                if expected != actual {
+                    println!("Peer {} presented an unexpected static key {}", connection.remote_x25519_id_hash, actual);
+                    return;
+                }
+            }
+
+            // A captured initiation must not be able to replace the current session or handshake
+            if payload.timestamp <= connection.latest_initiation_timestamp {
+                connection.stats.replayed_handshakes += 1;
+                return;
+            }
+            connection.latest_initiation_timestamp = payload.timestamp;
+            connection.set_endpoint(sender);
+            connection.path_validation = None;
+
+            connection.state = connection::State::Pending {
+                remote_public_key,
+                handshake_state: Some(Box::new(state)),
+                local_ratchet_secret: Some(EphemeralSecret::new(rng)),
+                remote_ratchet_public_key: Some(payload.ratchet_public_key),
+                remote_capabilities: payload.capabilities,
+                remote_padding_policy: payload.padding_policy,
+            };
+        }
+
+        let routing_id = connection.local_routing_id();
+        let (remote_public_key, handshake_state, local_ratchet_secret, remote_ratchet_public_key, remote_capabilities, remote_padding_policy) = match &mut connection.state {
+            connection::State::Pending {
+                remote_public_key,
+                handshake_state,
+                local_ratchet_secret,
+                remote_ratchet_public_key,
+                remote_capabilities,
+                remote_padding_policy,
+            } => (*remote_public_key, handshake_state, local_ratchet_secret, remote_ratchet_public_key, remote_capabilities, remote_padding_policy),
+            connection::State::Established { .. } => {
+                // Our keepalive got lost and the peer resent its final message
+                self.send_keepalive(socket, connection);
+                return;
+            },
+            connection::State::Failed { reason, .. } => {
+                println!("Received handshake message from {} after the connection failed: {}", connection.remote_x25519_id_hash, reason);
+                return;
+            },
+            connection::State::Disconnected { reason, .. } => {
+                println!("Received handshake message from {} after it disconnected: {}", connection.remote_x25519_id_hash, reason);
+                return;
+            },
+        };
+
+        if message_index != 0 {
+            let payload = match handshake_state {
+                // The peer resent its final message, our confirmation is retransmitted on its own
+                Some(state) if state.is_finished() => return,
+                Some(state) if state.message_index() == message_index as usize => {
+                    match state.read_message(message) {
+                        Ok(payload) => payload,
+                        Err(error) => {
+                            println!("Rejected handshake from {}: {}", connection.remote_x25519_id_hash, error);
+                            return;
+                        },
+                    }
+                },
+                _ => {
+                    println!("Received unexpected handshake message from {}", connection.remote_x25519_id_hash);
+                    return;
+                },
+            };
+            if connection.endpoint != Some(sender) {
+                connection.path_mtu = PathMtu::default();
+            }
+            connection.endpoint = Some(sender);
+            // The message answers ours, so the peer does receive what we send there
+            connection.amplification.validate(sender);
+
+            if message_index == 1 {
+                match bincode::deserialize::<HandshakePayload>(&payload) {
+                    Ok(payload) => {
+                        *remote_ratchet_public_key = Some(payload.ratchet_public_key);
+                        *remote_capabilities = payload.capabilities;
+                        *remote_padding_policy = payload.padding_policy;
+                    },
+                    Err(_) => {
+                        println!("Received malformed handshake payload from {}", connection.remote_x25519_id_hash);
+                        *handshake_state = None;
+                        return;
+                    },
+                }
+            }
+        }
+
+        let state = handshake_state.as_mut().unwrap();
+
+        if let (Some(expected), Some(actual)) = (remote_public_key, state.remote_static_public_key()) {
+            if expected != actual {
+                println!("Peer {} presented an unexpected static key {}", connection.remote_x25519_id_hash, actual);
+                *handshake_state = None;
+                return;
+            }
+        }
+
+        let mut sent = None;
+        if state.is_my_turn() {
+            let message_index = state.message_index() as u8;
+            let message = match self.write_handshake_message(state, local_ratchet_secret.as_ref().unwrap()) {
+                Ok(message) => message,
+                Err(error) => {
+                    println!("Failed to answer handshake from {}: {}", connection.remote_x25519_id_hash, error);
+                    self.fail(connection, FailureReason::KeyDerivationFailed);
+                    return;
+                },
+            };
+
+            sent = Some(Packet::new(
+                routing_id,
+                &connection.shared_mac_secret,
+                Data::Handshake {
+                    message_index,
+                    cookie_mac: [0u8; cookie_checker::COOKIE_SIZE],
+                    message
+                }
+            ).encode());
+        }
+
+        // The session is only established once the peer proved it has the same keys, so both
+        // sides send their proof and wait for the other one
+        let initiator = state.is_initiator();
+        let finished_mac = if state.is_finished() {
+            let role = if initiator { Role::Initiator } else { Role::Responder };
+            match Instance::finished_mac(state, role) {
+                Ok(mac) => Some(mac),
+                Err(reason) => {
+                    self.fail(connection, reason);
+                    return;
+                },
+            }
+        } else {
+            None
+        };
+
+        let endpoint = connection.endpoint.unwrap();
+        if let Some(data) = &sent {
+            Instance::send_to(socket, connection, data, endpoint);
+        }
+
+        let now = Instant::now();
+        let started_at = connection.retransmission.as_ref().map_or(now, |retransmission| retransmission.started_at);
+
+        let mac = match finished_mac {
+            Some(mac) => mac,
+            None => {
+                let retransmit = if initiator { Retransmit::Initiation } else { Retransmit::Wait };
+                connection.retransmission = Some(Retransmission::new(retransmit, started_at, now, &self.retransmit_policy, rng));
+                return;
+            },
+        };
+        let finished = Packet::new(
+            connection.local_routing_id(),
+            &connection.shared_mac_secret,
+            Data::HandshakeFinished {
+                mac
+            }
+        ).encode();
+        Instance::send_to(socket, connection, &finished, endpoint);
+
+        let packets = sent.into_iter().chain(std::iter::once(finished)).collect();
+        connection.retransmission = Some(Retransmission::new(Retransmit::FinalMessages(packets), started_at, now, &self.retransmit_policy, rng));
+    }
+
+    // Our ratchet public key and preferences travel in the first two messages
+    fn write_handshake_message(&self, state: &mut HandshakeState, ratchet_secret: &EphemeralSecret) -> Result<Vec<u8>, NoiseError> {
+        let payload = if state.message_index() <= 1 {
+            bincode::serialize(&HandshakePayload {
+                ratchet_public_key: ratchet_secret.public_key()?,
+                timestamp: HandshakePayload::now(),
+                padding_policy: self.padding_policy,
+                capabilities: self.capabilities,
+            }).unwrap()
+        } else {
+            vec![]
+        };
+
+        state.write_message(&payload)
+    }
+
+    // The initiator to responder and responder to initiator keys of a finished handshake
+    fn split_keys(state: &HandshakeState) -> Result<([u8; 32], [u8; 32]), NoiseError> {
+        let (initiator_to_responder, responder_to_initiator) = state.split()?;
+
+        Ok((
+            initiator_to_responder.key().ok_or(NoiseError::MissingKey)?,
+            responder_to_initiator.key().ok_or(NoiseError::MissingKey)?,
+        ))
+    }
+
+    fn finished_mac(state: &HandshakeState, role: Role) -> Result<[u8; HASH_SIZE], FailureReason> {
+        let (initiator_to_responder, responder_to_initiator) = Instance::split_keys(state)
+            .map_err(|_| FailureReason::KeyDerivationFailed)?;
+
+        SessionKeys::finished_mac(initiator_to_responder, responder_to_initiator, &state.handshake_hash(), role)
+            .map_err(|_| FailureReason::KeyDerivationFailed)
+    }
+
+    // Initiations make us spend a DH, so they are rate limited per address and, while we are busy,
+    // only answered when they carry a valid cookie for the address they come from
+    pub(super) fn admit_initiation(&mut self, socket: &UdpSocket, rng: &mut ThreadRng, connection: &mut Connection, sender: SocketAddr, parts: &[&[u8]], cookie_mac: &[u8; cookie_checker::COOKIE_SIZE]) -> bool {
+        let now = Instant::now();
+        if !self.rate_limiter.allow(sender.ip(), now) {
+            println!("Rate limited handshake from {} at {}", connection.remote_x25519_id_hash, sender);
+            return false;
+        }
+
+        if self.cookie_checker.is_busy(now) {
+            let cookie = self.cookie_checker.verify(sender, cookie_mac, parts, rng, now)
+                .and_then(|valid| if valid { Ok(None) } else { self.cookie_checker.cookie(sender, rng, now).map(Some) });
+            match cookie {
+                Ok(None) => {},
+                Ok(Some(cookie)) => {
+                    self.send_cookie_reply(socket, rng, connection, &cookie, sender);
+                    return false;
+                },
+                Err(error) => {
+                    println!("Failed to check the cookie of {} at {}: {}", connection.remote_x25519_id_hash, sender, error);
+                    return false;
+                },
+            }
+            // Only a peer that received our cookie reply can present the cookie
+            connection.amplification.validate(sender);
+        }
+
+        self.cookie_checker.record_handshake();
+        true
+    }
+
+    // A fresh key for every reply, so the nonce never repeats under the same key
+    fn cookie_key(connection: &Connection, nonce: &[u8]) -> Result<SessionKey, CryptoError> {
+        connection.shared_mac_secret.mac(&[COOKIE_KEY_LABEL, nonce])
+            .map(SessionKey::from)
+            .map_err(|_| CryptoError::Mac)
+    }
+
+    fn send_cookie_reply(&self, socket: &UdpSocket, rng: &mut ThreadRng, connection: &mut Connection, cookie: &[u8], sender: SocketAddr) {
+        let mut nonce = [0u8; 32];
+        rng.fill_bytes(&mut nonce);
+
+        let encrypted_cookie = Instance::cookie_key(connection, &nonce)
+            .and_then(|key| key.encrypt(0, connection.local_x25519_id_hash.as_ref(), cookie).map_err(|_| CryptoError::Encryption));
+        let encrypted_cookie = match encrypted_cookie {
+            Ok(encrypted_cookie) => encrypted_cookie,
+            Err(error) => {
+                println!("Failed to send cookie reply to {}: {}", connection.remote_x25519_id_hash, error);
+                return;
+            },
+        };
+
+        let data = Packet::new(
+            connection.local_routing_id(),
+            &connection.shared_mac_secret,
+            Data::CookieReply {
+                nonce,
+                encrypted_cookie
+            }
+        ).encode();
+        Instance::send_to(socket, connection, &data, sender);
+    }
+
+    pub(super) fn handle_cookie_reply(&self, connection: &mut Connection, nonce: &[u8], encrypted_cookie: &[u8]) {
+        if let connection::State::Pending { handshake_state: Some(state), .. } = &connection.state {
+            if !state.is_initiator() || state.message_index() != 1 {
+                return;
+            }
+        } else {
+            return;
+        }
+
+        let key = match Instance::cookie_key(connection, nonce) {
+            Ok(key) => key,
+            Err(error) => {
+                println!("Failed to open cookie reply from {}: {}", connection.remote_x25519_id_hash, error);
+                return;
+            },
+        };
+        match key.decrypt(0, connection.remote_x25519_id_hash.as_ref(), encrypted_cookie) {
+            Ok(cookie) if cookie.len() == cookie_checker::COOKIE_SIZE => {
+                println!("{} is busy, the next initiation carries its cookie", connection.remote_x25519_id_hash);
+
+                let mut slice = [0u8; cookie_checker::COOKIE_SIZE];
+                slice.copy_from_slice(&cookie);
+                connection.cookie = Some((Instant::now(), slice));
+            },
+            _ => println!("Received malformed cookie reply from {}", connection.remote_x25519_id_hash),
+        }
+    }
+
+    pub(super) fn handle_handshake_finished(&self, socket: &UdpSocket, rng: &mut ThreadRng, connection: &mut Connection, sender: SocketAddr, mac: &[u8; HASH_SIZE]) {
+        let expected = match &connection.state {
+            connection::State::Pending { handshake_state: Some(state), .. } if state.is_finished() => {
+                let role = if state.is_initiator() { Role::Responder } else { Role::Initiator };
+                Instance::finished_mac(state, role)
+            },
+            connection::State::Established { .. } => {
+                // Our keepalive got lost and the peer resent its confirmation
+                self.send_keepalive(socket, connection);
+                return;
+            },
+            _ => {
+                println!("Received unexpected key confirmation from {}", connection.remote_x25519_id_hash);
+                return;
+            },
+        };
+        match expected {
+            Ok(expected) if memcmp::eq(mac, &expected) => {},
+            Ok(_) => {
+                self.fail(connection, FailureReason::KeyConfirmationFailed);
+                return;
+            },
+            Err(reason) => {
+                self.fail(connection, reason);
+                return;
+            },
+        }
+
+        // The confirmation can only be computed from our handshake messages
+        if connection.endpoint == Some(sender) {
+            connection.amplification.validate(sender);
+        }
+
+        // Our own confirmation keeps being resent until the peer answers this keepalive
+        match self.establish(rng, connection) {
+            Ok(()) => {
+                self.send_keepalive(socket, connection);
+
+                for body in std::mem::take(&mut connection.queued_messages) {
+                    self.send_message(socket, connection, &body, Delivery::Reliable);
+                }
+            },
+            Err(reason) => self.fail(connection, reason),
+        }
+    }
+
+    fn establish(&self, rng: &mut ThreadRng, connection: &mut Connection) -> Result<(), FailureReason> {
+        let (remote_public_key, handshake_state, local_ratchet_secret, remote_ratchet_public_key, remote_capabilities, remote_padding_policy) = match &mut connection.state {
+            connection::State::Pending {
+                remote_public_key,
+                handshake_state: Some(handshake_state),
+                local_ratchet_secret: local_ratchet_secret @ Some(_),
+                remote_ratchet_public_key: Some(remote_ratchet_public_key),
+                remote_capabilities,
+                remote_padding_policy,
+            } => (*remote_public_key, handshake_state, local_ratchet_secret.take().unwrap(), *remote_ratchet_public_key, *remote_capabilities, *remote_padding_policy),
+            _ => return Err(FailureReason::KeyDerivationFailed),
+        };
+
+        let remote_static_public_key = handshake_state.remote_static_public_key().ok_or(FailureReason::KeyDerivationFailed)?;
+        let (initiator_to_responder, responder_to_initiator) = Instance::split_keys(handshake_state)
+            .map_err(|_| FailureReason::KeyDerivationFailed)?;
+        let (session_keys, ratchet_root_key) = SessionKeys::derive(initiator_to_responder, responder_to_initiator, &handshake_state.handshake_hash())
+            .map_err(|_| FailureReason::KeyDerivationFailed)?;
+
+        let (role, ratchet) = if handshake_state.is_initiator() {
+            (Role::Initiator, DoubleRatchet::new_initiator(ratchet_root_key, local_ratchet_secret, remote_ratchet_public_key))
+        } else {
+            (Role::Responder, DoubleRatchet::new_responder(rng, ratchet_root_key, local_ratchet_secret, remote_ratchet_public_key))
+        };
+        let ratchet = match ratchet {
+            Ok(ratchet) => ratchet,
+            Err(error) => {
+                println!("Failed to initialize ratchet with {}: {}", connection.remote_x25519_id_hash, error);
+                return Err(FailureReason::KeyDerivationFailed);
+            },
+        };
+
+        if remote_public_key.is_none() {
+            // First contact, from now on both sides address each other by the usual identifiers
+            connection.local_x25519_id_hash = x25519IDHash::new(self.public_key, connection.shared_mac_secret);
+            connection.remote_x25519_id_hash = x25519IDHash::new(remote_static_public_key, connection.shared_mac_secret);
+        }
+
+        let capabilities = self.capabilities.intersection(remote_capabilities);
+        let padding_policy = match role {
+            Role::Initiator => PaddingPolicy::negotiate(self.padding_policy, remote_padding_policy),
+            Role::Responder => PaddingPolicy::negotiate(remote_padding_policy, self.padding_policy),
+        };
+        println!("Established connection with {} using {:?} and {:?} padding", connection.remote_x25519_id_hash, capabilities, padding_policy);
+
+        // Replacing the pending state drops, and thereby erases, the ephemeral secrets
+        connection.state = connection::State::Established {
+            session: Box::new(Session::new(remote_static_public_key, role, session_keys, ratchet, self.congestion_control.controller())),
+            capabilities,
+            padding_policy,
+        };
+
+        self.tx.send(Response::Connected {
+            x25519_id_hash: connection.remote_x25519_id_hash
+        }).unwrap();
+
+        Ok(())
+    }
+
+    pub(super) fn retransmit_handshake(&self, socket: &UdpSocket, rng: &mut ThreadRng, connection: &mut Connection, now: Instant) {
+        let mut retransmission = match connection.retransmission.take() {
+            Some(retransmission) => retransmission,
+            None => return,
+        };
+
+        match &connection.state {
+            connection::State::Established { session, .. } if session.confirmed => return,
+            connection::State::Failed { .. } | connection::State::Disconnected { .. } => return,
+            _ => {},
+        }
+
+        if now >= retransmission.deadline(&self.retransmit_policy) {
+            self.fail(connection, FailureReason::HandshakeTimeout);
+            return;
+        }
+
+        if now >= retransmission.next_at {
+            if let Some(endpoint) = connection.endpoint {
+                match &retransmission.retransmit {
+                    Retransmit::Initiation => self.initiate_handshake(socket, rng, connection, endpoint),
+                    Retransmit::FinalMessages(packets) => {
+                        for data in packets {
+                            Instance::send_to(socket, connection, data, endpoint);
+                        }
+                    },
+                    Retransmit::Wait => {},
+                }
+            }
+
+            retransmission.back_off(now, &self.retransmit_policy, rng);
+        }
+
+        connection.retransmission = Some(retransmission);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{ Duration, Instant };
+    use rand::thread_rng;
+    use crate::{
+        x25519::EphemeralSecret,
+        x25519IDHash,
+        SharedMacSecret,
+        instance::{
+            Packet, Data, PacketError, SessionKeys, Role, HandshakePayload, Capability, Capabilities, PaddingPolicy,
+            CookieChecker, RateLimiter, cookie_checker::{ COOKIE_SIZE, COOKIE_LIFETIME }, RetransmitPolicy,
+        },
+    };
+
+    #[test]
+    fn capabilities_are_negotiated() {
+        let ours = Capabilities::all();
+        let theirs = Capabilities::default().with(Capability::ReliableDelivery).with(Capability::PathMtuDiscovery);
+        let agreed = ours.intersection(theirs);
+        assert!(agreed.contains(Capability::ReliableDelivery));
+        assert!(agreed.contains(Capability::PathMtuDiscovery));
+        assert!(!agreed.contains(Capability::Fragmentation));
+        assert_eq!(format!("{:?}", agreed), "{ReliableDelivery, PathMtuDiscovery}");
+
+        // Features of newer peers that we do not know never end up in the agreed set
+        let payload = HandshakePayload {
+            ratchet_public_key: EphemeralSecret::new(&mut thread_rng()).public_key().unwrap(),
+            timestamp: 0,
+            padding_policy: PaddingPolicy::None,
+            capabilities: ours,
+        };
+        let mut bytes = bincode::serialize(&payload).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] |= 0x80;
+        let newer = bincode::deserialize::<HandshakePayload>(&bytes).unwrap().capabilities;
+        assert_ne!(newer, ours);
+        assert_eq!(ours.intersection(newer), ours);
+    }
+
+    #[test]
+    fn cookies_are_bound_to_the_source_address() {
+        let mut rng = thread_rng();
+        let mut checker = CookieChecker::new(2, &mut rng);
+        let now = Instant::now();
+        let sender = "192.0.2.1:4000".parse().unwrap();
+        let parts: [&[u8]; 2] = [b"hash", b"initiation"];
+
+        let cookie = checker.cookie(sender, &mut rng, now).unwrap();
+        let cookie_mac = CookieChecker::mac(&cookie, &parts).unwrap();
+        assert!(checker.verify(sender, &cookie_mac, &parts, &mut rng, now).unwrap());
+        assert!(!checker.verify("192.0.2.1:4001".parse().unwrap(), &cookie_mac, &parts, &mut rng, now).unwrap());
+        assert!(!checker.verify("192.0.2.2:4000".parse().unwrap(), &cookie_mac, &parts, &mut rng, now).unwrap());
+        assert!(!checker.verify(sender, &cookie_mac, &[b"hash", b"other initiation"], &mut rng, now).unwrap());
+        assert!(!checker.verify(sender, &[0u8; COOKIE_SIZE], &parts, &mut rng, now).unwrap());
+        // The secret is replaced after a while
+        assert!(!checker.verify(sender, &cookie_mac, &parts, &mut rng, now + COOKIE_LIFETIME).unwrap());
+
+        assert!(!checker.is_busy(now));
+        checker.record_handshake();
+        checker.record_handshake();
+        assert!(checker.is_busy(now + Duration::from_millis(999)));
+        assert!(!checker.is_busy(now + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn handshakes_are_rate_limited_per_address() {
+        let mut limiter = RateLimiter::default();
+        let now = Instant::now();
+        let first = "192.0.2.1".parse().unwrap();
+        let second = "2001:db8::1".parse().unwrap();
+
+        for _ in 0..5 {
+            assert!(limiter.allow(first, now));
+        }
+        assert!(!limiter.allow(first, now));
+        assert!(limiter.allow(second, now));
+
+        // Tokens come back at 20 per second
+        assert!(!limiter.allow(first, now + Duration::from_millis(49)));
+        assert!(limiter.allow(first, now + Duration::from_millis(50)));
+        assert!(!limiter.allow(first, now + Duration::from_millis(50)));
+
+        limiter.expire(now + Duration::from_secs(1));
+        for _ in 0..5 {
+            assert!(limiter.allow(first, now + Duration::from_secs(1)));
+        }
+    }
+
+    #[test]
+    fn key_confirmation_is_bound_to_role_and_transcript() {
+        let (i, r, hash) = ([1u8; 32], [2u8; 32], [3u8; 32]);
+        let initiator = SessionKeys::finished_mac(i, r, &hash, Role::Initiator).unwrap();
+        let responder = SessionKeys::finished_mac(i, r, &hash, Role::Responder).unwrap();
+        assert_ne!(initiator, responder);
+        assert_eq!(initiator, SessionKeys::finished_mac(i, r, &hash, Role::Initiator).unwrap());
+        assert_ne!(initiator, SessionKeys::finished_mac(i, r, &[4u8; 32], Role::Initiator).unwrap());
+        assert_ne!(initiator, SessionKeys::finished_mac(r, i, &hash, Role::Initiator).unwrap());
+
+        let shared_mac_secret = SharedMacSecret::new(&mut thread_rng());
+        let bytes = Packet::new(x25519IDHash::from(vec![0u8; 32]), &shared_mac_secret, Data::HandshakeFinished { mac: initiator }).encode();
+        assert!(matches!(Packet::decode(&bytes).unwrap().data, Data::HandshakeFinished { mac } if mac == initiator));
+        let short = [&bytes[..bytes.len() - 33], &bytes[bytes.len() - 32..]].concat();
+        assert_eq!(Packet::decode(&short).err(), Some(PacketError::InvalidLength));
+    }
+
+    #[test]
+    fn retransmissions_back_off_up_to_the_maximum() {
+        let mut rng = thread_rng();
+        let policy = RetransmitPolicy::default();
+
+        for attempts in 0..32 {
+            let expected = policy.initial_interval.checked_mul(1 << attempts.min(16)).unwrap().min(policy.max_interval);
+            let delay = policy.delay(attempts, &mut rng);
+
+            assert!(delay >= expected);
+            assert!(delay <= expected + expected / 4);
+        }
+    }
+}
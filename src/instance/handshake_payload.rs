@@ -0,0 +1,24 @@
+use std::time::{ SystemTime, UNIX_EPOCH };
+use serde::{ Serialize, Deserialize };
+use crate::{
+    x25519::PublicKey,
+    instance::{ Capabilities, PaddingPolicy },
+};
+
+// Carried inside the first two Noise handshake messages
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct HandshakePayload {
+    pub ratchet_public_key: PublicKey,
+    // Nanoseconds since the epoch, a responder only accepts initiations newer than the last one
+    pub timestamp: u64,
+    // Padding the sender would like the session to use
+    pub padding_policy: PaddingPolicy,
+    // Features the sender is willing to use
+    pub capabilities: Capabilities,
+}
+
+impl HandshakePayload {
+    pub fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_nanos() as u64).unwrap_or(0)
+    }
+}
@@ -0,0 +1,29 @@
+use std::time::{ Duration, Instant };
+use crate::instance::Session;
+
+#[derive(Debug, Copy, Clone)]
+pub struct KeepalivePolicy {
+    // A keepalive goes out when nothing else was sent for this long
+    pub interval: Duration,
+    // The peer is considered gone when nothing arrived for this long
+    pub idle_timeout: Duration,
+}
+
+impl Default for KeepalivePolicy {
+    fn default() -> Self {
+        KeepalivePolicy {
+            interval: Duration::from_secs(10),
+            idle_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl KeepalivePolicy {
+    pub fn keepalive_due(&self, session: &Session, now: Instant) -> bool {
+        now.duration_since(session.last_sent_at) >= self.interval
+    }
+
+    pub fn idle(&self, session: &Session, now: Instant) -> bool {
+        now.duration_since(session.last_received_at) >= self.idle_timeout
+    }
+}
@@ -0,0 +1,104 @@
+use std::fmt::{ Formatter, Debug, Error };
+use openssl::error::ErrorStack;
+use crate::{
+    hkdf::{ self, HASH_SIZE },
+    instance::SessionKey,
+};
+
+const REKEY_LABEL: &[u8] = b"chat-test rekey";
+const RATCHET_ROOT_LABEL: &[u8] = b"chat-test ratchet root";
+const NEXT_INITIATOR_TO_RESPONDER_LABEL: &[u8] = b"chat-test next initiator to responder";
+const NEXT_RESPONDER_TO_INITIATOR_LABEL: &[u8] = b"chat-test next responder to initiator";
+const INITIATOR_FINISHED_LABEL: &[u8] = b"chat-test initiator finished";
+const RESPONDER_FINISHED_LABEL: &[u8] = b"chat-test responder finished";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Role {
+    Initiator,
+    Responder,
+}
+
+pub struct SessionKeys {
+    pub initiator_to_responder: SessionKey,
+    pub responder_to_initiator: SessionKey,
+    pub rekey_secret: [u8; HASH_SIZE],
+}
+
+impl SessionKeys {
+    // The traffic keys are the two Noise `Split()` outputs, the remaining secrets are expanded
+    // from both of them with the handshake hash as the salt. The ratchet root key is returned
+    // separately since it is only needed once, to seed the Double Ratchet.
+    pub fn derive(initiator_to_responder: [u8; 32], responder_to_initiator: [u8; 32], handshake_hash: &[u8]) -> Result<(Self, [u8; HASH_SIZE]), ErrorStack> {
+        let prk = SessionKeys::extract(initiator_to_responder, responder_to_initiator, handshake_hash)?;
+
+        let mut rekey_secret = [0u8; HASH_SIZE];
+        hkdf::expand(&prk, REKEY_LABEL, &mut rekey_secret)?;
+        let mut ratchet_root_key = [0u8; HASH_SIZE];
+        hkdf::expand(&prk, RATCHET_ROOT_LABEL, &mut ratchet_root_key)?;
+
+        Ok((SessionKeys {
+            initiator_to_responder: SessionKey::from(initiator_to_responder),
+            responder_to_initiator: SessionKey::from(responder_to_initiator),
+            rekey_secret,
+        }, ratchet_root_key))
+    }
+
+    fn extract(initiator_to_responder: [u8; 32], responder_to_initiator: [u8; 32], handshake_hash: &[u8]) -> Result<[u8; HASH_SIZE], ErrorStack> {
+        let mut ikm = Vec::with_capacity(64);
+        ikm.extend_from_slice(&initiator_to_responder);
+        ikm.extend_from_slice(&responder_to_initiator);
+
+        hkdf::extract(handshake_hash, &ikm)
+    }
+
+    // MAC over the handshake hash with a key from the same schedule, so it proves that `role`
+    // completed the handshake with the same transcript and derived the same keys
+    pub fn finished_mac(initiator_to_responder: [u8; 32], responder_to_initiator: [u8; 32], handshake_hash: &[u8], role: Role) -> Result<[u8; HASH_SIZE], ErrorStack> {
+        let prk = SessionKeys::extract(initiator_to_responder, responder_to_initiator, handshake_hash)?;
+
+        let mut finished_key = [0u8; HASH_SIZE];
+        match role {
+            Role::Initiator => hkdf::expand(&prk, INITIATOR_FINISHED_LABEL, &mut finished_key)?,
+            Role::Responder => hkdf::expand(&prk, RESPONDER_FINISHED_LABEL, &mut finished_key)?,
+        }
+
+        hkdf::hmac(&finished_key, &[handshake_hash])
+    }
+
+    // Keys of the following generation, both peers arrive at the same ones without exchanging
+    // anything but the generation number
+    pub fn next(&self) -> Result<Self, ErrorStack> {
+        let mut initiator_to_responder = [0u8; 32];
+        hkdf::expand(&self.rekey_secret, NEXT_INITIATOR_TO_RESPONDER_LABEL, &mut initiator_to_responder)?;
+        let mut responder_to_initiator = [0u8; 32];
+        hkdf::expand(&self.rekey_secret, NEXT_RESPONDER_TO_INITIATOR_LABEL, &mut responder_to_initiator)?;
+        let mut rekey_secret = [0u8; HASH_SIZE];
+        hkdf::expand(&self.rekey_secret, REKEY_LABEL, &mut rekey_secret)?;
+
+        Ok(SessionKeys {
+            initiator_to_responder: SessionKey::from(initiator_to_responder),
+            responder_to_initiator: SessionKey::from(responder_to_initiator),
+            rekey_secret,
+        })
+    }
+
+    pub fn sending_key(&self, role: Role) -> &SessionKey {
+        match role {
+            Role::Initiator => &self.initiator_to_responder,
+            Role::Responder => &self.responder_to_initiator,
+        }
+    }
+
+    pub fn receiving_key(&self, role: Role) -> &SessionKey {
+        match role {
+            Role::Initiator => &self.responder_to_initiator,
+            Role::Responder => &self.initiator_to_responder,
+        }
+    }
+}
+
+impl Debug for SessionKeys {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.write_str("\"<session keys>\"")
+    }
+}
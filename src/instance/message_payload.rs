@@ -0,0 +1,11 @@
+use serde::{ Serialize, Deserialize };
+use crate::ratchet::Header;
+
+// Carried inside `Data::Message`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MessagePayload {
+    // Only reliable messages are numbered
+    pub sequence: Option<u64>,
+    pub header: Header,
+    pub ciphertext: Vec<u8>,
+}
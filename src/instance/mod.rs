@@ -8,15 +8,18 @@ use std::{
         Receiver,
         channel
     },
-    time::Duration,
+    time::{ Duration, Instant },
     thread::JoinHandle,
     collections::HashMap,
+    borrow::Cow,
+};
+use rand::{
+    prelude::{ thread_rng, ThreadRng },
+    RngCore,
 };
-use rand::prelude::thread_rng;
-use openssl::sha::Sha256;
 
 use crate::{
-    x25519::{PrivateKey, PublicKey, SharedKey},
+    x25519::{ PrivateKey, PublicKey },
     x25519IDHash,
 };
 
@@ -25,18 +28,157 @@ pub use packet::{
     Packet,
     Data
 };
+mod packet_error;
+pub use packet_error::PacketError;
 mod command;
 pub use command::Command;
 mod response;
 pub use response::Response;
 mod connection;
 pub use connection::Connection;
-mod ephemeral_blob;
-pub use ephemeral_blob::EphemeralBlob;
+mod connection_summary;
+pub use connection_summary::ConnectionSummary;
+mod session_key;
+pub use session_key::SessionKey;
+mod key_schedule;
+pub use key_schedule::{ SessionKeys, Role };
+mod handshake_payload;
+pub use handshake_payload::HandshakePayload;
+mod capabilities;
+pub use capabilities::{ Capability, Capabilities };
+mod cookie_checker;
+pub use cookie_checker::CookieChecker;
+mod rate_limiter;
+pub use rate_limiter::RateLimiter;
+mod drop_counter;
+pub use drop_counter::DropCounter;
+mod amplification_limit;
+pub use amplification_limit::AmplificationLimit;
+mod obfuscation_key;
+pub use obfuscation_key::ObfuscationKey;
+mod padding_policy;
+pub use padding_policy::PaddingPolicy;
+mod sealed;
+pub use sealed::Sealed;
+mod message_payload;
+pub use message_payload::MessagePayload;
+mod rekey_payload;
+pub use rekey_payload::RekeyPayload;
+mod rekey_policy;
+pub use rekey_policy::RekeyPolicy;
+mod session;
+pub use session::Session;
+mod session_error;
+pub use session_error::SessionError;
+mod replay_window;
+pub use replay_window::ReplayWindow;
+mod connection_stats;
+pub use connection_stats::ConnectionStats;
+mod path_validation;
+pub use path_validation::PathValidation;
+mod retransmit_policy;
+pub use retransmit_policy::RetransmitPolicy;
+mod retransmission;
+pub use retransmission::{ Retransmission, Retransmit };
+mod failure_reason;
+pub use failure_reason::FailureReason;
+mod timers;
+pub use timers::Timers;
+mod keepalive_policy;
+pub use keepalive_policy::KeepalivePolicy;
+mod disconnect_reason;
+pub use disconnect_reason::DisconnectReason;
+mod delivery;
+pub use delivery::Delivery;
+mod ack_payload;
+pub use ack_payload::AckPayload;
+mod rtt_estimator;
+pub use rtt_estimator::RttEstimator;
+mod send_buffer;
+pub use send_buffer::SendBuffer;
+mod receive_buffer;
+pub use receive_buffer::ReceiveBuffer;
+mod fragment_payload;
+pub use fragment_payload::FragmentPayload;
+mod reassembly;
+pub use reassembly::Reassembly;
+mod reassembly_error;
+pub use reassembly_error::ReassemblyError;
+mod path_mtu;
+pub use path_mtu::PathMtu;
+mod probe_payload;
+pub use probe_payload::ProbePayload;
+mod congestion_controller;
+pub use congestion_controller::{ CongestionController, CongestionControl };
+mod new_reno;
+pub use new_reno::NewReno;
+mod cubic;
+pub use cubic::Cubic;
+mod pacer;
+pub use pacer::Pacer;
+
+// What `Instance` does for each part of the protocol
+mod handshake;
+mod rekey;
+mod reliability;
+mod path_mtu_discovery;
+#[cfg(test)]
+mod test_util;
+
+// The protocol version is appended, so a handshake whose packets were downgraded to another
+// version does not complete
+const PROLOGUE: &[u8] = b"chat-test";
+
+// Bind sealed payloads to the `Data` variant they were sent in
+const MESSAGE_LABEL: &[u8] = b"message";
+const REKEY_LABEL: &[u8] = b"rekey";
+const PATH_CHALLENGE_LABEL: &[u8] = b"path challenge";
+const PATH_RESPONSE_LABEL: &[u8] = b"path response";
+const KEEPALIVE_LABEL: &[u8] = b"keepalive";
+const ACK_LABEL: &[u8] = b"ack";
+const FRAGMENT_LABEL: &[u8] = b"fragment";
+const PMTU_PROBE_LABEL: &[u8] = b"pmtu probe";
+const PMTU_ACK_LABEL: &[u8] = b"pmtu ack";
+
+// Cookie replies are encrypted with a key derived from the shared MAC secret and this label
+const COOKIE_KEY_LABEL: &[u8] = b"chat-test cookie";
+
+// Large enough for any UDP datagram, so nothing gets cut off
+const RECEIVE_BUFFER_SIZE: usize = 1 << 16;
+// Packet header, sealing and MAC around a sealed payload
+const SEALED_PACKET_OVERHEAD: usize = 98;
+// The fragment fields on top of that
+const FRAGMENT_OVERHEAD: usize = SEALED_PACKET_OVERHEAD + 20;
+// Room for the ratchet header and the sequence number
+const MAX_BODY_SIZE: usize = reassembly::MAX_MESSAGE_SIZE - 256;
+
+// How long to wait for a rekey acknowledgement before asking again
+const REKEY_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+// Path challenges are repeated a few times before giving up on the new address
+const PATH_CHALLENGE_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_PATH_CHALLENGES: u8 = 3;
+
+// Messages waiting for a session beyond this are dropped
+const MAX_QUEUED_MESSAGES: usize = 64;
+
+// Initiations per second that need a DH before initiators have to present a cookie
+const BUSY_THRESHOLD: u32 = 32;
+
+// Retransmission timeouts in a row after which full-sized packets are assumed to be dropped
+const BLACK_HOLE_TIMEOUTS: u32 = 3;
 
 pub struct InstanceBuilder {
     control_address: SocketAddr,
     protocol_address: SocketAddr,
+    rekey_policy: RekeyPolicy,
+    retransmit_policy: RetransmitPolicy,
+    keepalive_policy: KeepalivePolicy,
+    congestion_control: CongestionControl,
+    capabilities: Capabilities,
+    busy_threshold: u32,
+    obfuscation: bool,
+    padding_policy: PaddingPolicy,
 }
 
 impl Default for InstanceBuilder {
@@ -44,6 +186,14 @@ impl Default for InstanceBuilder {
         InstanceBuilder {
             control_address: "127.0.0.1:65500".parse().unwrap(),
             protocol_address: "0.0.0.0:6555".parse().unwrap(),
+            rekey_policy: RekeyPolicy::default(),
+            retransmit_policy: RetransmitPolicy::default(),
+            keepalive_policy: KeepalivePolicy::default(),
+            congestion_control: CongestionControl::default(),
+            capabilities: Capabilities::all(),
+            busy_threshold: BUSY_THRESHOLD,
+            obfuscation: false,
+            padding_policy: PaddingPolicy::default(),
         }
     }
 }
@@ -64,16 +214,111 @@ impl InstanceBuilder {
 
         self
     }
+
+    pub fn set_rekey_after_messages(mut self, messages: u64) -> Self {
+        self.rekey_policy.after_messages = messages;
+
+        self
+    }
+
+    pub fn set_rekey_after_bytes(mut self, bytes: u64) -> Self {
+        self.rekey_policy.after_bytes = bytes;
+
+        self
+    }
+
+    pub fn set_rekey_after_time(mut self, time: Duration) -> Self {
+        self.rekey_policy.after_time = time;
+
+        self
+    }
+
+    pub fn set_session_lifetime(mut self, session_lifetime: Duration) -> Self {
+        self.rekey_policy.session_lifetime = session_lifetime;
+
+        self
+    }
+
+    pub fn set_handshake_retransmit_interval(mut self, interval: Duration) -> Self {
+        self.retransmit_policy.initial_interval = interval;
+
+        self
+    }
+
+    pub fn set_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.retransmit_policy.timeout = timeout;
+
+        self
+    }
+
+    pub fn set_keepalive_interval(mut self, interval: Duration) -> Self {
+        self.keepalive_policy.interval = interval;
+
+        self
+    }
+
+    pub fn set_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.keepalive_policy.idle_timeout = idle_timeout;
+
+        self
+    }
+
+    pub fn set_congestion_control(mut self, congestion_control: CongestionControl) -> Self {
+        self.congestion_control = congestion_control;
+
+        self
+    }
+
+    // Features offered to peers, only those both sides offer are used
+    pub fn set_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+
+        self
+    }
+
+    pub fn set_busy_threshold(mut self, handshakes_per_second: u32) -> Self {
+        self.busy_threshold = handshakes_per_second;
+
+        self
+    }
+
+    // Both peers have to agree on this, an obfuscated packet cannot be told apart from noise
+    pub fn set_obfuscation(mut self, obfuscation: bool) -> Self {
+        self.obfuscation = obfuscation;
+
+        self
+    }
+
+    // Proposed to every peer, see `PaddingPolicy::negotiate` for which one a session ends up with
+    pub fn set_padding_policy(mut self, padding_policy: PaddingPolicy) -> Self {
+        self.padding_policy = padding_policy;
+
+        self
+    }
 }
 
 pub struct Instance {
     control_address: SocketAddr, // @TODO currently unused, could accept command response through it?
     protocol_address: SocketAddr,
+    rekey_policy: RekeyPolicy,
+    retransmit_policy: RetransmitPolicy,
+    keepalive_policy: KeepalivePolicy,
+    congestion_control: CongestionControl,
+    capabilities: Capabilities,
+    cookie_checker: CookieChecker,
+    rate_limiter: RateLimiter,
+    drop_counter: DropCounter,
+    obfuscation: bool,
+    padding_policy: PaddingPolicy,
     private_key: PrivateKey,
     public_key: PublicKey,
     rx: Receiver<Command>,
     tx: Sender<Response>,
-    connections: HashMap<x25519IDHash, Connection>
+    connections: HashMap<x25519IDHash, Connection>,
+    // Identifiers seen on the wire, mapped to the identifiers connections are kept under
+    routes: HashMap<x25519IDHash, x25519IDHash>,
+    routes_epoch: u64,
+    timers: Timers,
 }
 
 impl Instance {
@@ -84,137 +329,879 @@ impl Instance {
         (Instance {
             control_address: instance_builder.control_address,
             protocol_address: instance_builder.protocol_address,
+            rekey_policy: instance_builder.rekey_policy,
+            retransmit_policy: instance_builder.retransmit_policy,
+            keepalive_policy: instance_builder.keepalive_policy,
+            congestion_control: instance_builder.congestion_control,
+            capabilities: instance_builder.capabilities,
+            cookie_checker: CookieChecker::new(instance_builder.busy_threshold, &mut thread_rng()),
+            rate_limiter: RateLimiter::default(),
+            drop_counter: DropCounter::default(),
+            obfuscation: instance_builder.obfuscation,
+            padding_policy: instance_builder.padding_policy,
             private_key,
             public_key,
             rx: instance_rx,
             tx: instance_tx,
-            connections: HashMap::new()
+            connections: HashMap::new(),
+            routes: HashMap::new(),
+            routes_epoch: 0,
+            timers: Timers::default(),
         }, (return_tx, return_rx))
     }
 
+    // Obfuscated packets are padded as far as the path has room for
+    fn send_to(socket: &UdpSocket, connection: &mut Connection, data: &[u8], endpoint: SocketAddr) {
+        let room = connection.path_mtu.plpmtu.saturating_sub(data.len() + connection.obfuscation_overhead());
+        Instance::send_padded(socket, connection, data, endpoint, room);
+    }
+
+    // Everything sent on behalf of a connection goes through here, so an address that has not
+    // proven it receives our traffic only gets a bounded multiple of what it sent
+    fn send_padded(socket: &UdpSocket, connection: &mut Connection, data: &[u8], endpoint: SocketAddr, room: usize) {
+        let data = match &connection.obfuscation {
+            Some(key) => match key.obfuscate(&mut thread_rng(), data, room) {
+                Ok(bytes) => Cow::Owned(bytes),
+                Err(error) => {
+                    println!("Failed to obfuscate packet to {}: {}", connection.remote_x25519_id_hash, error);
+                    return;
+                },
+            },
+            None => Cow::Borrowed(data),
+        };
+        if !connection.amplification.allows(endpoint, data.len()) {
+            connection.stats.amplification_limited_packets += 1;
+            return;
+        }
+
+        connection.amplification.sent(endpoint, data.len());
+        // Losing a packet is nothing the protocol does not already handle
+        if let Err(error) = socket.send_to(&data, endpoint) {
+            connection.stats.send_errors += 1;
+            println!("Failed to send to {} at {}: {}", connection.remote_x25519_id_hash, endpoint, error);
+        }
+    }
+
+    fn send_sealed(&self, socket: &UdpSocket, connection: &mut Connection, label: &[u8], plaintext: &[u8], data: fn(Sealed) -> Data, endpoint: SocketAddr) {
+        match connection.seal(label, plaintext) {
+            Ok(sealed) => {
+                let data = Packet::new(
+                    connection.local_routing_id(),
+                    &connection.shared_mac_secret,
+                    data(sealed)
+                ).encode();
+                // Probes have to be exactly the size they test
+                if label == PMTU_PROBE_LABEL {
+                    Instance::send_padded(socket, connection, &data, endpoint, 0);
+                } else {
+                    Instance::send_to(socket, connection, &data, endpoint);
+                }
+            },
+            Err(error) => println!("Failed to send to {}: {}", connection.remote_x25519_id_hash, error),
+        }
+    }
+
+    fn handle_path_response(&self, connection: &mut Connection, sender: SocketAddr, response: &[u8]) {
+        if let Some(validation) = connection.path_validation {
+            if validation.endpoint == sender && validation.attempts > 0 && validation.challenge[..] == *response {
+                println!("Connection {} moved to {}", connection.remote_x25519_id_hash, sender);
+
+                connection.set_endpoint(sender);
+                connection.amplification.validate(sender);
+                connection.path_validation = None;
+            }
+        }
+    }
+
+    fn validate_path(&self, socket: &UdpSocket, rng: &mut ThreadRng, connection: &mut Connection, now: Instant) {
+        let mut validation = match connection.path_validation {
+            Some(validation) => validation,
+            None => return,
+        };
+
+        if let Some(sent_at) = validation.sent_at {
+            if now.duration_since(sent_at) < PATH_CHALLENGE_INTERVAL {
+                return;
+            }
+        }
+        if validation.attempts >= MAX_PATH_CHALLENGES {
+            println!("Connection {} did not confirm {}", connection.remote_x25519_id_hash, validation.endpoint);
+            connection.path_validation = None;
+            return;
+        }
+
+        if validation.attempts == 0 {
+            rng.fill_bytes(&mut validation.challenge);
+        }
+        validation.sent_at = Some(now);
+        validation.attempts += 1;
+        connection.path_validation = Some(validation);
+
+        self.send_sealed(socket, connection, PATH_CHALLENGE_LABEL, &validation.challenge, Data::PathChallenge, validation.endpoint);
+    }
+
+    // Only authentic packets get an answer, so this cannot be used to reflect traffic at others
+    fn reject_version(&mut self, socket: &UdpSocket, packet: &[u8], hash: x25519IDHash, version: u8, sender: SocketAddr) {
+        let connection = match self.routes.get(&hash) {
+            Some(x25519_id_hash) => self.connections.get_mut(x25519_id_hash),
+            None => None,
+        };
+        match connection {
+            Some(connection) if Packet::authenticates(packet, &connection.shared_mac_secret) => {
+                println!("Rejected packet with unsupported protocol version {} from {}", version, hash);
+                connection.amplification.received(sender, packet.len());
+
+                let data = Packet::new(
+                    connection.local_routing_id(),
+                    &connection.shared_mac_secret,
+                    Data::VersionNegotiation {
+                        versions: packet::SUPPORTED_VERSIONS.to_vec(),
+                    }
+                ).encode();
+                Instance::send_to(socket, connection, &data, sender);
+            },
+            _ => self.drop_packet(),
+        }
+    }
+
+    // For packets that may well be forged, a log line each would let anyone flood the output
+    fn drop_packet(&mut self) {
+        if let Some(dropped) = self.drop_counter.record(Instant::now()) {
+            println!("Dropped {} unauthenticated or malformed packets", dropped);
+        }
+    }
+
+    // Obfuscated packets start with the routing identifier, which tells whose key to remove the
+    // obfuscation with
+    fn reveal(&self, bytes: &[u8]) -> Option<Vec<u8>> {
+        let x25519_id_hash = self.routes.get(&x25519IDHash::from(bytes.get(..32)?.to_vec()))?;
+
+        self.connections.get(x25519_id_hash)?.obfuscation.as_ref()?.reveal(bytes)
+    }
+
+    // Every connection is reachable under the identifiers of the previous, current and next epoch,
+    // so neither clock skew nor packets crossing an epoch boundary get dropped
+    fn routes(connections: &HashMap<x25519IDHash, Connection>, epoch: u64) -> HashMap<x25519IDHash, x25519IDHash> {
+        connections.values().flat_map(|connection| {
+            (epoch.saturating_sub(1)..=epoch + 1).map(move |epoch| {
+                (connection.remote_x25519_id_hash.routing_id(&connection.shared_mac_secret, epoch), connection.remote_x25519_id_hash)
+            })
+        }).collect()
+    }
+
+    fn update_routes(&mut self) {
+        self.routes_epoch = x25519IDHash::current_epoch();
+        self.routes = Instance::routes(&self.connections, self.routes_epoch);
+    }
+
+    fn handle_version_negotiation(&self, connection: &mut Connection, versions: &[u8]) {
+        // A peer that understands our version has no reason to reject it, so the packet is stale
+        if versions.contains(&packet::VERSION) {
+            return;
+        }
+
+        println!("{} only supports protocol versions {:?}", connection.remote_x25519_id_hash, versions);
+        if let connection::State::Pending { .. } = connection.state {
+            self.fail(connection, FailureReason::UnsupportedVersion);
+        }
+    }
+
+    fn fail(&self, connection: &mut Connection, reason: FailureReason) {
+        println!("Connection with {} failed: {}", connection.remote_x25519_id_hash, reason);
+
+        connection.state = connection::State::Failed {
+            remote_public_key: connection.remote_public_key(),
+            reason,
+        };
+        connection.retransmission = None;
+        connection.path_validation = None;
+        connection.queued_messages.clear();
+
+        self.tx.send(Response::ConnectionFailed {
+            x25519_id_hash: connection.remote_x25519_id_hash,
+            reason
+        }).unwrap();
+    }
+
+    fn disconnect(&self, connection: &mut Connection, remote_public_key: PublicKey, reason: DisconnectReason) {
+        println!("Disconnected from {}: {}", connection.remote_x25519_id_hash, reason);
+
+        // Dropping the session erases its keys, a new handshake is needed once the peer is back
+        connection.state = connection::State::Disconnected {
+            remote_public_key,
+            reason,
+        };
+        connection.retransmission = None;
+        connection.path_validation = None;
+
+        self.tx.send(Response::Disconnected {
+            x25519_id_hash: connection.remote_x25519_id_hash,
+            reason
+        }).unwrap();
+    }
+
+    // Runs whatever is due for the connection and returns when it needs to be looked at again
+    fn maintain(&self, socket: &UdpSocket, rng: &mut ThreadRng, connection: &mut Connection, now: Instant) -> Option<Instant> {
+        self.retransmit_handshake(socket, rng, connection, now);
+        self.validate_path(socket, rng, connection, now);
+        self.maintain_session(socket, rng, connection, now);
+        self.retransmit_messages(socket, connection, now);
+        self.send_queued_messages(socket, connection, now);
+        self.probe_path_mtu(socket, connection, now);
+
+        let mut wakeups = Vec::new();
+        if let Some(retransmission) = &connection.retransmission {
+            wakeups.push(retransmission.next_at);
+            wakeups.push(retransmission.deadline(&self.retransmit_policy));
+        }
+        if let Some(sent_at) = connection.path_validation.and_then(|validation| validation.sent_at) {
+            wakeups.push(sent_at + PATH_CHALLENGE_INTERVAL);
+        }
+        if let connection::State::Established { session, .. } = &connection.state {
+            wakeups.extend(connection.path_mtu.next_timeout());
+            wakeups.push(session.rekeyed_at + self.rekey_policy.after_time);
+            wakeups.push(session.established_at + self.rekey_policy.session_lifetime);
+            if let Some(requested_at) = session.rekey_requested_at {
+                wakeups.push(requested_at + REKEY_RETRY_INTERVAL);
+            }
+            wakeups.push(session.last_sent_at + self.keepalive_policy.interval);
+            wakeups.push(session.last_received_at + self.keepalive_policy.idle_timeout);
+            wakeups.extend(session.send_buffer.next_timeout(session.rtt.rto));
+            if !session.send_buffer.queued.is_empty() {
+                wakeups.extend(session.pacer.next_send_at);
+            }
+            wakeups.extend(session.reassembly.next_timeout());
+        }
+
+        wakeups.into_iter().min()
+    }
+
     fn run_threaded(&mut self) {
         let socket = UdpSocket::bind(self.protocol_address).unwrap();
         socket.set_read_timeout(Some(Duration::from_millis(5))).unwrap();
         let mut rng = thread_rng();
 
+        let mut data = vec![0u8; RECEIVE_BUFFER_SIZE];
         loop {
-            let mut data = [0u8; 4096];
-            if let Ok((_, sender)) = socket.recv_from(&mut data) {
-                if let Ok(packet) = bincode::deserialize::<Packet>(&data) {
-                    if let Some(connection) = self.connections.get_mut(&packet.hash) {
-                        if connection.endpoint.is_none() {
-                            connection.endpoint = Some(sender)
-                        } else if connection.endpoint != Some(sender) {
-                            connection.endpoint = Some(sender)
-                        } // @TODO Denial of Service?
-
-                        match packet.data {
-                            Data::Handshake { ephemeral_blob } => {
-                                let mut ready_to_establish = false;
-
-                                if let connection::State::Pending {
-                                    remote_public_key,
-                                    local_ephemeral_blob,
-                                    remote_ephemeral_blob,
-                                    sent_handshake,
-                                } = &mut connection.state {
-                                    if remote_ephemeral_blob.is_some() {
-                                        println!("Received handshake from {} multiple times!", remote_public_key)
-                                    } else {
-                                        *remote_ephemeral_blob = Some(ephemeral_blob);
-                                        ready_to_establish = true;
-                                    }
+            if x25519IDHash::current_epoch() != self.routes_epoch {
+                self.update_routes();
+            }
 
-                                    if !*sent_handshake {
-                                        let data = bincode::serialize(&Packet {
-                                            hash: connection.local_x25519_id_hash,
-                                            data: Data::Handshake {
-                                                ephemeral_blob: local_ephemeral_blob.unwrap()
-                                            }
-                                        }).unwrap();
-                                        socket.send_to(data.as_slice(), sender).unwrap();
-                                        *sent_handshake = true;
-                                    }
-                                }
-
-                                if ready_to_establish {
-                                    if let connection::State::Pending {
-                                        remote_public_key,
-                                        local_ephemeral_blob,
-                                        remote_ephemeral_blob,
-                                        sent_handshake
-                                    } = connection.state {
-                                        let shared_key = SharedKey::derive(&self.private_key, &remote_public_key);
-
-                                        let mut sha256 = Sha256::new();
-                                        sha256.update(shared_key.as_ref());
-
-                                        if local_ephemeral_blob.as_ref().unwrap() > remote_ephemeral_blob.as_ref().unwrap() {
-                                            sha256.update(local_ephemeral_blob.unwrap().as_ref());
-                                            sha256.update(remote_ephemeral_blob.unwrap().as_ref());
-                                        } else {
-                                            sha256.update(remote_ephemeral_blob.unwrap().as_ref());
-                                            sha256.update(local_ephemeral_blob.unwrap().as_ref());
-                                        }
+            if let Ok((size, sender)) = socket.recv_from(&mut data) {
+                let bytes = if self.obfuscation {
+                    self.reveal(&data[..size]).map(Cow::Owned)
+                } else {
+                    Some(Cow::Borrowed(&data[..size]))
+                };
+
+                match bytes.as_deref().map(Packet::decode) {
+                    Some(Ok(packet)) => {
+                        let route = self.routes.get(&packet.hash).copied();
 
-                                        println!("{}", base64::encode(&sha256.finish()));
+                        // Nothing about the connection may change before the packet is known to come from the peer
+                        let authentic = match route.and_then(|x25519_id_hash| self.connections.get(&x25519_id_hash)) {
+                            Some(connection) => packet.verify(&connection.shared_mac_secret),
+                            None => false,
+                        };
 
-                                        connection.state = connection::State::Established { placeholder: () };
+                        if !authentic {
+                            self.drop_packet();
+                        } else if let Some(mut connection) = route.and_then(|x25519_id_hash| self.connections.remove(&x25519_id_hash)) {
+                            let x25519_id_hash = connection.remote_x25519_id_hash;
+                            connection.amplification.received(sender, size);
+
+                            // The peer is still sending after we gave up on it, so set the session up again
+                            let resumed = match (&connection.state, &packet.data) {
+                                (connection::State::Disconnected { .. }, Data::Handshake { .. }) => false,
+                                (connection::State::Disconnected { .. }, Data::VersionNegotiation { .. }) => false,
+                                (connection::State::Disconnected { .. }, Data::HandshakeFinished { .. }) => false,
+                                (connection::State::Disconnected { .. }, Data::CookieReply { .. }) => false,
+                                (connection::State::Disconnected { .. }, _) => true,
+                                _ => false,
+                            };
+                            if let (true, Some(endpoint)) = (resumed, connection.endpoint) {
+                                self.start_handshake(&socket, &mut rng, &mut connection, endpoint, Instant::now());
+                            }
+
+                            match packet.data {
+                                Data::Handshake { message_index, cookie_mac, message } => {
+                                    let parts: [&[u8]; 3] = [x25519_id_hash.as_ref(), &[message_index], &message];
+                                    if message_index != 0 || self.admit_initiation(&socket, &mut rng, &mut connection, sender, &parts, &cookie_mac) {
+                                        self.handle_handshake(&socket, &mut rng, &mut connection, sender, message_index, message.as_slice());
+                                    }
+                                },
+                                Data::CookieReply { nonce, encrypted_cookie } => {
+                                    self.handle_cookie_reply(&mut connection, &nonce, &encrypted_cookie);
+                                },
+                                Data::HandshakeFinished { mac } => {
+                                    self.handle_handshake_finished(&socket, &mut rng, &mut connection, sender, &mac);
+                                },
+                                Data::VersionNegotiation { versions } => {
+                                    self.handle_version_negotiation(&mut connection, &versions);
+                                },
+                                Data::Message(sealed) => {
+                                    if let Some(plaintext) = connection.open(MESSAGE_LABEL, &sealed, Some(sender)) {
+                                        self.handle_message(&socket, &mut rng, &mut connection, x25519_id_hash, &plaintext);
                                     }
-                                }
-                            },
+                                },
+                                Data::PmtuProbe(sealed) => {
+                                    if let Some(plaintext) = connection.open(PMTU_PROBE_LABEL, &sealed, Some(sender)) {
+                                        self.handle_probe(&socket, &mut connection, &plaintext);
+                                    }
+                                },
+                                Data::PmtuAck(sealed) => {
+                                    if let Some(plaintext) = connection.open(PMTU_ACK_LABEL, &sealed, Some(sender)) {
+                                        self.handle_probe_ack(&mut connection, &plaintext);
+                                    }
+                                },
+                                Data::Fragment(sealed) => {
+                                    if let Some(plaintext) = connection.open(FRAGMENT_LABEL, &sealed, Some(sender)) {
+                                        match bincode::deserialize::<FragmentPayload>(&plaintext) {
+                                            Ok(fragment) => {
+                                                if let Some(message) = self.handle_fragment(&mut connection, fragment) {
+                                                    self.handle_message(&socket, &mut rng, &mut connection, x25519_id_hash, &message);
+                                                }
+                                            },
+                                            Err(_) => println!("Received malformed fragment from {}", x25519_id_hash),
+                                        }
+                                    }
+                                },
+                                Data::Rekey(sealed) => {
+                                    if let Some(plaintext) = connection.open(REKEY_LABEL, &sealed, Some(sender)) {
+                                        match bincode::deserialize::<RekeyPayload>(&plaintext) {
+                                            Ok(payload) => self.handle_rekey(&socket, &mut connection, payload),
+                                            Err(_) => println!("Received malformed rekey from {}", x25519_id_hash),
+                                        }
+                                    }
+                                },
+                                Data::PathChallenge(sealed) => {
+                                    // Answered on the path it arrived on, which is the one being validated
+                                    if let Some(challenge) = connection.open(PATH_CHALLENGE_LABEL, &sealed, None) {
+                                        self.send_sealed(&socket, &mut connection, PATH_RESPONSE_LABEL, &challenge, Data::PathResponse, sender);
+                                    }
+                                },
+                                Data::PathResponse(sealed) => {
+                                    if let Some(response) = connection.open(PATH_RESPONSE_LABEL, &sealed, None) {
+                                        self.handle_path_response(&mut connection, sender, &response);
+                                    }
+                                },
+                                Data::Keepalive(sealed) => {
+                                    connection.open(KEEPALIVE_LABEL, &sealed, Some(sender));
+                                },
+                                Data::Ack(sealed) => {
+                                    if let Some(plaintext) = connection.open(ACK_LABEL, &sealed, Some(sender)) {
+                                        match bincode::deserialize::<AckPayload>(&plaintext) {
+                                            Ok(ack) => self.handle_ack(&mut connection, &ack),
+                                            Err(_) => println!("Received malformed acknowledgement from {}", x25519_id_hash),
+                                        }
+                                    }
+                                },
+                            }
+
+                            // The identifiers change once first contact is established
+                            let rerouted = connection.remote_x25519_id_hash != x25519_id_hash;
+                            self.timers.schedule(Instant::now(), connection.remote_x25519_id_hash);
+                            self.connections.insert(connection.remote_x25519_id_hash, connection);
+                            if rerouted {
+                                self.update_routes();
+                            }
                         }
-                    }
+                    },
+                    Some(Err(PacketError::UnsupportedVersion { hash, version })) => {
+                        self.reject_version(&socket, bytes.as_deref().unwrap(), hash, version, sender);
+                    },
+                    Some(Err(_)) | None => self.drop_packet(),
                 }
             }
 
-            if let Ok(command) = self.rx.recv_timeout(Duration::from_millis(5)) {
+            // Waiting is left to the socket, so bursts of datagrams are read without pausing
+            if let Ok(command) = self.rx.try_recv() {
                 match command {
                     Command::Exit => { break },
                     Command::AddConnection { public_key, shared_mac_secret } => {
-                        let remote_x5519_id_hash = x25519IDHash::new(public_key, shared_mac_secret);
-                        self.connections.insert(remote_x5519_id_hash.clone(), Connection {
-                            local_x25519_id_hash: x25519IDHash::new(self.public_key, shared_mac_secret),
-                            remote_x25519_id_hash: remote_x5519_id_hash,
-                            endpoint: None,
-                            state: connection::State::Pending {
-                                remote_public_key: public_key,
-                                local_ephemeral_blob: Some(EphemeralBlob::new(&mut rng)),
-                                remote_ephemeral_blob: None,
-                                sent_handshake: false
-                            },
-                        });
+                        let (local_x25519_id_hash, remote_x25519_id_hash) = match public_key {
+                            Some(public_key) => (x25519IDHash::new(self.public_key, shared_mac_secret), x25519IDHash::new(public_key, shared_mac_secret)),
+                            None => (x25519IDHash::first_contact(shared_mac_secret), x25519IDHash::first_contact(shared_mac_secret)),
+                        };
+
+                        let obfuscation = if self.obfuscation { Some(ObfuscationKey::new(&shared_mac_secret).unwrap()) } else { None };
+                        self.connections.insert(remote_x25519_id_hash, Connection::new(local_x25519_id_hash, remote_x25519_id_hash, shared_mac_secret, public_key, obfuscation));
+                        self.update_routes();
                     },
                     Command::ListConnections => {
-                        self.tx.send(Response::ListConnections { connections: self.connections.clone() }).unwrap();
+                        let connections = self.connections.iter().map(|(x25519_id_hash, connection)| (*x25519_id_hash, connection.summary())).collect();
+                        self.tx.send(Response::ListConnections { connections }).unwrap();
                     },
                     Command::Connect { x25519_id_hash, endpoint } => {
-                        if let Some(connection) = self.connections.get_mut(&x25519_id_hash) {
-                            if let connection::State::Pending {
-                                remote_public_key,
-                                local_ephemeral_blob,
-                                remote_ephemeral_blob,
-                                sent_handshake
-                            } = &mut connection.state {
-                                connection.endpoint = Some(endpoint);
-                                *sent_handshake = true;
-
-                                let data = bincode::serialize(&Packet {
-                                    hash: connection.local_x25519_id_hash,
-                                    data: Data::Handshake {
-                                        ephemeral_blob: local_ephemeral_blob.unwrap()
+                        if let Some(mut connection) = self.connections.remove(&x25519_id_hash) {
+                            if let connection::State::Pending { handshake_state: Some(_), .. } = &connection.state {
+                                println!("Handshake with {} is already in progress", x25519_id_hash);
+                            } else {
+                                let now = Instant::now();
+                                self.start_handshake(&socket, &mut rng, &mut connection, endpoint, now);
+                                self.timers.schedule(now, x25519_id_hash);
+                            }
+
+                            self.connections.insert(x25519_id_hash, connection);
+                        }
+                    },
+                    Command::SendMessage { x25519_id_hash, body, delivery } => {
+                        if let Some(mut connection) = self.connections.remove(&x25519_id_hash) {
+                            match (&connection.state, delivery) {
+                                (connection::State::Established { .. }, _) => self.send_message(&socket, &mut connection, &body, delivery),
+                                // Traffic resumes, the message goes out once the new session is up
+                                (connection::State::Disconnected { .. }, Delivery::Reliable)
+                                | (connection::State::Pending { handshake_state: Some(_), .. }, Delivery::Reliable) => {
+                                    if connection.queued_messages.len() < MAX_QUEUED_MESSAGES {
+                                        connection.queued_messages.push(body);
+                                    } else {
+                                        println!("Too many messages queued for {}", x25519_id_hash);
                                     }
-                                }).unwrap();
-                                socket.send_to(data.as_slice(), endpoint).unwrap();
+
+                                    if let (connection::State::Disconnected { .. }, Some(endpoint)) = (&connection.state, connection.endpoint) {
+                                        self.start_handshake(&socket, &mut rng, &mut connection, endpoint, Instant::now());
+                                    }
+                                },
+                                _ => println!("Connection {} is not established", x25519_id_hash),
                             }
+
+                            // Sending may have used up the current keys
+                            self.timers.schedule(Instant::now(), x25519_id_hash);
+                            self.connections.insert(x25519_id_hash, connection);
                         }
                     },
                 }
             }
+
+            let now = Instant::now();
+            for x25519_id_hash in self.timers.expired(now) {
+                if let Some(mut connection) = self.connections.remove(&x25519_id_hash) {
+                    let wakeup = self.maintain(&socket, &mut rng, &mut connection, now);
+                    if let Some(wakeup) = wakeup {
+                        self.timers.schedule(wakeup, connection.remote_x25519_id_hash);
+                    }
+                    self.connections.insert(connection.remote_x25519_id_hash, connection);
+                }
+            }
         }
     }
 
     pub fn run(mut self) -> JoinHandle<()> {
         std::thread::spawn(move || { self.run_threaded() })
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{ SocketAddr, UdpSocket },
+        sync::mpsc::{ Sender, Receiver },
+        thread::JoinHandle,
+        collections::HashMap,
+        time::{ Instant, Duration, UNIX_EPOCH },
+    };
+    use rand::{ thread_rng, prelude::ThreadRng, RngCore };
+    use crate::{
+        x25519::{ PrivateKey, PublicKey },
+        instance::{
+            InstanceBuilder, Command, Response, Delivery,
+            SessionError, ReplayWindow, replay_window::WINDOW_SIZE, Packet, Data, PacketError,
+            packet::{ MAGIC, VERSION },
+            connection::State, Timers, Capabilities, DropCounter, cookie_checker::COOKIE_SIZE,
+            AmplificationLimit, ObfuscationKey, obfuscation_key::{ MAX_PADDING, OVERHEAD as OBFUSCATION_OVERHEAD }, PaddingPolicy,
+            Sealed, session_key::TAG_SIZE,
+            ProbePayload, path_mtu::{ BASE_PLPMTU, MAX_PLPMTU },
+        },
+        x25519IDHash,
+        x25519_id_hash::EPOCH_LENGTH,
+        SharedMacSecret,
+    };
+    use super::{ Instance, SEALED_PACKET_OVERHEAD, FRAGMENT_OVERHEAD, PMTU_PROBE_LABEL, test_util::{ connection, fragment, pair } };
+
+    #[test]
+    fn sealed_payloads_are_bound_to_their_label_and_generation() {
+        let (mut alice, mut bob) = pair();
+
+        let sealed = alice.seal(b"message", b"", b"hello").unwrap();
+        assert!(bob.open(b"rekey", b"", &sealed).is_err());
+
+        let mut future = sealed.clone();
+        future.generation = 1;
+        assert!(bob.open(b"message", b"", &future).is_err());
+        assert_eq!(bob.generation, 0);
+    }
+
+    #[test]
+    fn replay_window() {
+        let mut window = ReplayWindow::default();
+
+        for counter in &[0, 1, 5, 3, 2] {
+            window.check(*counter).unwrap();
+            window.update(*counter);
+        }
+        for counter in &[0, 1, 2, 3, 5] {
+            assert!(matches!(window.check(*counter), Err(SessionError::Duplicate)));
+        }
+        window.check(4).unwrap();
+
+        window.update(WINDOW_SIZE + 4);
+        assert!(matches!(window.check(4), Err(SessionError::TooOld)));
+        assert!(matches!(window.check(5), Err(SessionError::Duplicate)));
+        window.check(6).unwrap();
+
+        // Jumping far ahead clears everything that was remembered
+        window.update(100 * WINDOW_SIZE);
+        window.check(100 * WINDOW_SIZE - 1).unwrap();
+        assert!(matches!(window.check(WINDOW_SIZE + 4), Err(SessionError::TooOld)));
+    }
+
+    #[test]
+    fn replayed_packets_are_rejected() {
+        let (mut alice, mut bob) = pair();
+
+        let first = alice.seal(b"message", b"", b"first").unwrap();
+        let second = alice.seal(b"message", b"", b"second").unwrap();
+        assert_eq!(second.counter, first.counter + 1);
+
+        // Out of order is fine, twice is not
+        assert_eq!(bob.open(b"message", b"", &second).unwrap(), b"second");
+        assert_eq!(bob.open(b"message", b"", &first).unwrap(), b"first");
+        assert!(matches!(bob.open(b"message", b"", &first), Err(SessionError::Duplicate)));
+
+        // A forged packet must not move the window
+        let mut forged = second.clone();
+        forged.counter = 1000;
+        assert!(matches!(bob.open(b"message", b"", &forged), Err(SessionError::Decryption)));
+        let third = alice.seal(b"message", b"", b"third").unwrap();
+        assert_eq!(bob.open(b"message", b"", &third).unwrap(), b"third");
+    }
+
+    #[test]
+    fn packets_are_authenticated() {
+        let mut rng = thread_rng();
+        let shared_mac_secret = SharedMacSecret::new(&mut rng);
+        let hash = x25519IDHash::first_contact(shared_mac_secret);
+
+        let packet = Packet::new(hash, &shared_mac_secret, Data::Handshake { message_index: 0, cookie_mac: [0u8; COOKIE_SIZE], message: vec![1, 2, 3] });
+        let packet = Packet::decode(&packet.encode()).unwrap();
+        assert!(packet.verify(&shared_mac_secret));
+        assert!(!packet.verify(&SharedMacSecret::new(&mut rng)));
+
+        let mut tampered = Packet::new(hash, &shared_mac_secret, Data::Handshake { message_index: 0, cookie_mac: [0u8; COOKIE_SIZE], message: vec![1, 2, 3] });
+        tampered.data = Data::Handshake { message_index: 1, cookie_mac: [0u8; COOKIE_SIZE], message: vec![1, 2, 3] };
+        assert!(!tampered.verify(&shared_mac_secret));
+
+        let mut redirected = Packet::new(hash, &shared_mac_secret, Data::Handshake { message_index: 0, cookie_mac: [0u8; COOKIE_SIZE], message: vec![1, 2, 3] });
+        redirected.hash = x25519IDHash::first_contact(SharedMacSecret::new(&mut rng));
+        assert!(!redirected.verify(&shared_mac_secret));
+    }
+
+    #[test]
+    fn dropped_packets_are_reported_in_batches() {
+        let mut counter = DropCounter::default();
+        let now = Instant::now();
+
+        assert_eq!(counter.record(now), Some(1));
+        for _ in 0..100 {
+            assert_eq!(counter.record(now + Duration::from_secs(1)), None);
+        }
+        assert_eq!(counter.record(now + Duration::from_secs(10)), Some(101));
+        assert_eq!(counter.record(now + Duration::from_secs(11)), None);
+    }
+
+    #[test]
+    fn unvalidated_addresses_receive_at_most_three_times_their_traffic() {
+        let mut limit = AmplificationLimit::default();
+        let spoofed = "192.0.2.1:1000".parse().unwrap();
+        let other = "192.0.2.2:1000".parse().unwrap();
+
+        assert!(!limit.allows(spoofed, 1));
+        limit.received(spoofed, 100);
+        assert!(limit.allows(spoofed, 300));
+        assert!(!limit.allows(spoofed, 301));
+        assert!(!limit.allows(other, 1));
+
+        limit.sent(spoofed, 250);
+        assert!(!limit.allows(spoofed, 51));
+        limit.received(spoofed, 20);
+        assert!(limit.allows(spoofed, 110));
+
+        limit.validate(spoofed);
+        assert!(limit.allows(spoofed, 1 << 20));
+        // Traffic from another address starts its own budget without affecting the validated one
+        limit.received(other, 10);
+        assert!(limit.allows(other, 30) && !limit.allows(other, 31));
+        assert!(limit.allows(spoofed, 1 << 20));
+    }
+
+    #[test]
+    fn wire_format_is_stable() {
+        let shared_mac_secret = SharedMacSecret::new(&mut thread_rng());
+        let hash = x25519IDHash::from(vec![7u8; 32]);
+
+        let sealed = Sealed { generation: 1, counter: 2, ciphertext: vec![3, 4] };
+        let bytes = Packet::new(hash, &shared_mac_secret, Data::Ack(sealed)).encode();
+        assert_eq!(&bytes[..4], b"CHAT");
+        assert_eq!(bytes[4], VERSION);
+        assert_eq!(&bytes[5..37], &[7u8; 32]);
+        assert_eq!(&bytes[37..52], &[6, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2, 3, 4]);
+        assert_eq!(bytes.len(), 52 + 32);
+        assert!(Packet::authenticates(&bytes, &shared_mac_secret));
+        match Packet::decode(&bytes).unwrap().data {
+            Data::Ack(sealed) => assert_eq!((sealed.generation, sealed.counter, sealed.ciphertext), (1, 2, vec![3, 4])),
+            _ => panic!("decoded the wrong type"),
+        }
+
+        let bytes = Packet::new(hash, &shared_mac_secret, Data::Handshake { message_index: 2, cookie_mac: [9u8; COOKIE_SIZE], message: vec![5] }).encode();
+        assert_eq!(&bytes[37..39], &[0, 2]);
+        assert_eq!(&bytes[39..55], &[9u8; COOKIE_SIZE]);
+        assert_eq!(bytes[55], 5);
+
+        let bytes = Packet::new(hash, &shared_mac_secret, Data::VersionNegotiation { versions: vec![VERSION] }).encode();
+        assert_eq!(bytes[4], 0);
+        assert!(matches!(Packet::decode(&bytes).unwrap().data, Data::VersionNegotiation { versions } if versions == vec![VERSION]));
+
+        // Packets of other versions can still be authenticated and answered
+        let mut future = [&MAGIC[..], &[VERSION + 1], &[7u8; 32], b"anything"].concat();
+        let mac = shared_mac_secret.mac(&[&future]).unwrap();
+        future.extend_from_slice(&mac);
+        assert_eq!(Packet::decode(&future).err(), Some(PacketError::UnsupportedVersion { hash, version: VERSION + 1 }));
+        assert!(Packet::authenticates(&future, &shared_mac_secret));
+
+        let mut unknown = [&MAGIC[..], &[VERSION], &[7u8; 32], &[200]].concat();
+        unknown.extend_from_slice(&mac);
+        assert_eq!(Packet::decode(&unknown).err(), Some(PacketError::UnknownType(200)));
+        assert_eq!(Packet::decode(&[b"QUIC", &unknown[4..]].concat()).err(), Some(PacketError::BadMagic));
+        assert_eq!(Packet::decode(&unknown[..60]).err(), Some(PacketError::Truncated));
+    }
+
+    #[test]
+    fn only_the_newest_authentic_packet_starts_path_validation() {
+        let mut rng = thread_rng();
+        let (mut alice, bob) = pair();
+
+        let shared_mac_secret = SharedMacSecret::new(&mut rng);
+        let x25519_id_hash = x25519IDHash::first_contact(shared_mac_secret);
+        let old_endpoint = "127.0.0.1:1000".parse().unwrap();
+        let new_endpoint = "127.0.0.1:2000".parse().unwrap();
+        let mut connection = connection(shared_mac_secret);
+        connection.endpoint = Some(old_endpoint);
+        connection.state = State::Established { session: Box::new(bob), capabilities: Capabilities::all(), padding_policy: PaddingPolicy::None };
+
+        let first = alice.seal(b"message", x25519_id_hash.as_ref(), b"first").unwrap();
+        let second = alice.seal(b"message", x25519_id_hash.as_ref(), b"second").unwrap();
+        let mut forged = alice.seal(b"message", x25519_id_hash.as_ref(), b"third").unwrap();
+        forged.ciphertext[0] ^= 1;
+
+        connection.open(b"message", &second, Some(old_endpoint)).unwrap();
+        assert!(connection.open(b"message", &forged, Some(new_endpoint)).is_none());
+        assert!(connection.path_validation.is_none());
+
+        // Reordered packets are no sign of the peer moving
+        connection.open(b"message", &first, Some(new_endpoint)).unwrap();
+        assert!(connection.path_validation.is_none());
+        assert!(connection.open(b"message", &second, Some(new_endpoint)).is_none());
+        assert!(connection.path_validation.is_none());
+        assert_eq!(connection.stats.duplicate_packets, 1);
+        assert_eq!(connection.stats.rejected_packets, 1);
+
+        let fourth = alice.seal(b"message", x25519_id_hash.as_ref(), b"fourth").unwrap();
+        connection.open(b"message", &fourth, Some(new_endpoint)).unwrap();
+        assert_eq!(connection.path_validation.unwrap().endpoint, new_endpoint);
+        assert_eq!(connection.endpoint, Some(old_endpoint));
+    }
+
+    #[test]
+    fn timers_fire_once_at_the_earliest_wakeup() {
+        let mut timers = Timers::default();
+        let now = Instant::now();
+        let first = x25519IDHash::from(vec![1u8; 32]);
+        let second = x25519IDHash::from(vec![2u8; 32]);
+
+        timers.schedule(now + Duration::from_secs(2), first);
+        timers.schedule(now + Duration::from_secs(1), first);
+        timers.schedule(now + Duration::from_secs(3), first);
+        timers.schedule(now + Duration::from_secs(2), second);
+
+        assert!(timers.expired(now).is_empty());
+        assert_eq!(timers.expired(now + Duration::from_secs(1)), vec![first]);
+        assert_eq!(timers.expired(now + Duration::from_secs(5)), vec![second]);
+        assert!(timers.expired(now + Duration::from_secs(10)).is_empty());
+    }
+
+    fn sealed_packet_size(data: fn(Sealed) -> Data, plaintext: &[u8]) -> usize {
+        let sealed = Sealed {
+            generation: u32::MAX,
+            counter: u64::MAX,
+            ciphertext: vec![0u8; plaintext.len() + TAG_SIZE],
+        };
+        let packet = Packet::new(x25519IDHash::from(vec![0u8; 32]), &SharedMacSecret::new(&mut thread_rng()), data(sealed));
+
+        packet.encode().len()
+    }
+
+    #[test]
+    fn packet_overheads() {
+        assert_eq!(sealed_packet_size(Data::Message, &[0u8; 100]), 100 + SEALED_PACKET_OVERHEAD);
+
+        let fragment = bincode::serialize(&fragment(u64::MAX, u16::MAX, u16::MAX, &[0u8; 100])).unwrap();
+        assert_eq!(sealed_packet_size(Data::Fragment, &fragment), 100 + FRAGMENT_OVERHEAD);
+
+        for size in &[BASE_PLPMTU, 1337, MAX_PLPMTU] {
+            let probe = bincode::serialize(&ProbePayload::padded(*size, 0)).unwrap();
+            assert_eq!(sealed_packet_size(Data::PmtuProbe, &probe), *size);
+        }
+
+        // Probes are sent at exactly the size they test, whatever the padding policy adds
+        for padding_policy in &[PaddingPolicy::None, PaddingPolicy::PowerOfTwo, PaddingPolicy::Block(64), PaddingPolicy::Random(32)] {
+            let (alice, _) = pair();
+            let mut connection = connection(SharedMacSecret::new(&mut thread_rng()));
+            connection.state = State::Established { session: Box::new(alice), capabilities: Capabilities::all(), padding_policy: *padding_policy };
+
+            for size in &[BASE_PLPMTU, 1337, MAX_PLPMTU] {
+                let probe = bincode::serialize(&ProbePayload::padded(*size, padding_policy.overhead())).unwrap();
+                let sealed = connection.seal(PMTU_PROBE_LABEL, &probe).unwrap();
+                let datagram = Packet::new(connection.local_routing_id(), &connection.shared_mac_secret, Data::PmtuProbe(sealed)).encode();
+                assert_eq!(datagram.len(), *size);
+            }
+        }
+    }
+
+    #[test]
+    fn routing_ids_rotate_every_epoch() {
+        let mut rng = thread_rng();
+        let shared_mac_secret = SharedMacSecret::new(&mut rng);
+        let x25519_id_hash = x25519IDHash::first_contact(shared_mac_secret);
+
+        let start = UNIX_EPOCH + EPOCH_LENGTH * 1000;
+        let epoch = x25519IDHash::epoch(start);
+        assert_eq!(x25519IDHash::epoch(start + EPOCH_LENGTH - Duration::from_secs(1)), epoch);
+        assert_eq!(x25519IDHash::epoch(start + EPOCH_LENGTH), epoch + 1);
+
+        // Stable within an epoch, unrelated across epochs, connections and to the identifier itself
+        let routing_id = x25519_id_hash.routing_id(&shared_mac_secret, epoch);
+        assert_eq!(routing_id, x25519_id_hash.routing_id(&shared_mac_secret, epoch));
+        assert_ne!(routing_id, x25519_id_hash);
+        assert_ne!(routing_id, x25519_id_hash.routing_id(&shared_mac_secret, epoch + 1));
+        assert_ne!(routing_id, x25519_id_hash.routing_id(&SharedMacSecret::new(&mut rng), epoch));
+
+        let mut connections = HashMap::new();
+        connections.insert(x25519_id_hash, connection(shared_mac_secret));
+
+        // A peer one epoch ahead or behind is still understood
+        let routes = Instance::routes(&connections, epoch);
+        assert_eq!(routes.len(), 3);
+        for epoch in epoch - 1..=epoch + 1 {
+            assert_eq!(routes.get(&x25519_id_hash.routing_id(&shared_mac_secret, epoch)), Some(&x25519_id_hash));
+        }
+        assert_eq!(routes.get(&x25519_id_hash.routing_id(&shared_mac_secret, epoch + 2)), None);
+        assert_eq!(routes.get(&x25519_id_hash), None);
+    }
+
+    #[test]
+    fn obfuscation_hides_the_header_and_pads() {
+        let mut rng = thread_rng();
+        let shared_mac_secret = SharedMacSecret::new(&mut rng);
+        let key = ObfuscationKey::new(&shared_mac_secret).unwrap();
+        let hash = x25519IDHash::first_contact(shared_mac_secret);
+
+        let bytes = Packet::new(hash, &shared_mac_secret, Data::HandshakeFinished { mac: [7u8; 32] }).encode();
+        let obfuscated = key.obfuscate(&mut rng, &bytes, 1000).unwrap();
+        assert_eq!(&obfuscated[..32], hash.as_ref());
+        assert!(obfuscated.len() >= bytes.len() + OBFUSCATION_OVERHEAD && obfuscated.len() <= bytes.len() + OBFUSCATION_OVERHEAD + MAX_PADDING);
+        assert!(!obfuscated.windows(32).any(|window| window == [7u8; 32]));
+        assert_eq!(key.reveal(&obfuscated).unwrap(), bytes);
+
+        // Padding never grows a packet beyond the room it was given
+        let first = key.obfuscate(&mut rng, &bytes, 0).unwrap();
+        assert_eq!(first.len(), bytes.len() + OBFUSCATION_OVERHEAD);
+
+        // A retransmitted packet does not look like the one sent before
+        let second = key.obfuscate(&mut rng, &bytes, 0).unwrap();
+        assert_ne!(first[32..first.len() - 32], second[32..second.len() - 32]);
+        assert_eq!(key.reveal(&second).unwrap(), bytes);
+
+        // Without the right key the packet does not come out intact
+        let other = ObfuscationKey::new(&SharedMacSecret::new(&mut rng)).unwrap();
+        assert!(other.reveal(&obfuscated).is_none_or(|revealed| !Packet::decode(&revealed).is_ok_and(|packet| packet.verify(&shared_mac_secret))));
+        assert!(key.reveal(&obfuscated[..40]).is_none());
+    }
+
+    #[test]
+    fn padding_hides_the_length_of_payloads() {
+        let mut rng = thread_rng();
+
+        assert_eq!(PaddingPolicy::None.pad(&mut rng, &[1u8; 100], 1000).len(), 100);
+        assert_eq!(PaddingPolicy::PowerOfTwo.pad(&mut rng, &[1u8; 100], 1000).len(), 128);
+        assert_eq!(PaddingPolicy::PowerOfTwo.pad(&mut rng, &[1u8; 127], 1000).len(), 128);
+        assert_eq!(PaddingPolicy::PowerOfTwo.pad(&mut rng, &[1u8; 128], 1000).len(), 256);
+        assert_eq!(PaddingPolicy::Block(64).pad(&mut rng, &[1u8; 100], 1000).len(), 128);
+        assert_eq!(PaddingPolicy::Block(64).pad(&mut rng, &[], 1000).len(), 64);
+        for _ in 0..100 {
+            let length = PaddingPolicy::Random(32).pad(&mut rng, &[1u8; 100], 1000).len();
+            assert!(length > 100 && length <= 133);
+        }
+
+        // The path limits the padding, but the marker is always there
+        assert_eq!(PaddingPolicy::PowerOfTwo.pad(&mut rng, &[1u8; 600], 1000).len(), 1000);
+        assert_eq!(PaddingPolicy::Block(64).pad(&mut rng, &[1u8; 1000], 1000).len(), 1001);
+
+        // Trailing zeros of the payload itself survive, whatever the policy
+        for policy in &[PaddingPolicy::PowerOfTwo, PaddingPolicy::Block(16), PaddingPolicy::Random(8)] {
+            for plaintext in &[vec![], vec![0u8; 5], vec![1, 0x80, 0, 0]] {
+                assert_eq!(policy.unpad(policy.pad(&mut rng, plaintext, 1000)).as_ref(), Some(plaintext));
+            }
+        }
+        assert_eq!(PaddingPolicy::Block(16).unpad(vec![1, 2, 0, 0]), None);
+        assert_eq!(PaddingPolicy::Block(16).unpad(vec![0; 16]), None);
+
+        assert_eq!(PaddingPolicy::negotiate(PaddingPolicy::None, PaddingPolicy::Block(64)), PaddingPolicy::Block(64));
+        assert_eq!(PaddingPolicy::negotiate(PaddingPolicy::PowerOfTwo, PaddingPolicy::None), PaddingPolicy::PowerOfTwo);
+        assert_eq!(PaddingPolicy::negotiate(PaddingPolicy::PowerOfTwo, PaddingPolicy::Block(64)), PaddingPolicy::PowerOfTwo);
+    }
+
+    fn spawn(rng: &mut ThreadRng) -> (PublicKey, SocketAddr, Sender<Command>, Receiver<Response>, JoinHandle<()>) {
+        let mut secret = [0u8; 128];
+        rng.fill_bytes(&mut secret);
+        let private_key = PrivateKey::new(&secret).unwrap();
+        let public_key = PublicKey::new(&secret).unwrap();
+
+        // Whatever port the system hands out is free for the instance to bind right after
+        let protocol_address = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let (instance, (tx, rx)) = Instance::new(InstanceBuilder::new().set_protocol_address(protocol_address), private_key, public_key);
+
+        (public_key, protocol_address, tx, rx, instance.run())
+    }
+
+    fn receive(rx: &Receiver<Response>) -> Response {
+        rx.recv_timeout(Duration::from_secs(5)).expect("no response from the instance")
+    }
+
+    #[test]
+    fn instances_exchange_messages_over_loopback() {
+        let mut rng = thread_rng();
+        let shared_mac_secret = SharedMacSecret::new(&mut rng);
+        let (alice_public_key, _, alice_tx, alice_rx, alice) = spawn(&mut rng);
+        let (bob_public_key, bob_address, bob_tx, bob_rx, bob) = spawn(&mut rng);
+        let alice_id = x25519IDHash::new(alice_public_key, shared_mac_secret);
+        let bob_id = x25519IDHash::new(bob_public_key, shared_mac_secret);
+
+        alice_tx.send(Command::AddConnection { public_key: Some(bob_public_key), shared_mac_secret }).unwrap();
+        bob_tx.send(Command::AddConnection { public_key: Some(alice_public_key), shared_mac_secret }).unwrap();
+        alice_tx.send(Command::Connect { x25519_id_hash: bob_id, endpoint: bob_address }).unwrap();
+
+        assert!(matches!(receive(&alice_rx), Response::Connected { x25519_id_hash } if x25519_id_hash == bob_id));
+        assert!(matches!(receive(&bob_rx), Response::Connected { x25519_id_hash } if x25519_id_hash == alice_id));
+
+        alice_tx.send(Command::SendMessage { x25519_id_hash: bob_id, body: "hello bob".to_string(), delivery: Delivery::Reliable }).unwrap();
+        bob_tx.send(Command::SendMessage { x25519_id_hash: alice_id, body: "hello alice".to_string(), delivery: Delivery::Reliable }).unwrap();
+
+        assert!(matches!(receive(&bob_rx), Response::Message { x25519_id_hash, body } if x25519_id_hash == alice_id && body == "hello bob"));
+        assert!(matches!(receive(&alice_rx), Response::Message { x25519_id_hash, body } if x25519_id_hash == bob_id && body == "hello alice"));
+
+        alice_tx.send(Command::Exit).unwrap();
+        bob_tx.send(Command::Exit).unwrap();
+        alice.join().unwrap();
+        bob.join().unwrap();
+    }
+}
@@ -0,0 +1,68 @@
+use std::time::Instant;
+use crate::instance::{
+    RttEstimator,
+    CongestionController,
+    path_mtu::BASE_PLPMTU,
+};
+
+pub const INITIAL_WINDOW: usize = 10 * BASE_PLPMTU;
+pub const MIN_WINDOW: usize = 2 * BASE_PLPMTU;
+
+// NewReno as described in RFC 9002, section 7
+#[derive(Debug, Copy, Clone)]
+pub struct NewReno {
+    pub window: usize,
+    pub ssthresh: usize,
+    // Losses of messages sent before this belong to the congestion event that was already handled
+    pub recovery_start: Option<Instant>,
+}
+
+impl Default for NewReno {
+    fn default() -> Self {
+        NewReno {
+            window: INITIAL_WINDOW,
+            ssthresh: usize::MAX,
+            recovery_start: None,
+        }
+    }
+}
+
+impl NewReno {
+    fn in_recovery(&self, sent_at: Instant) -> bool {
+        self.recovery_start.is_some_and(|recovery_start| sent_at <= recovery_start)
+    }
+}
+
+impl CongestionController for NewReno {
+    fn window(&self) -> usize {
+        self.window
+    }
+
+    fn on_ack(&mut self, bytes: usize, sent_at: Instant, _: &RttEstimator, _: Instant) {
+        if self.in_recovery(sent_at) {
+            return;
+        }
+
+        if self.window < self.ssthresh {
+            self.window += bytes;
+        } else {
+            self.window += BASE_PLPMTU * bytes / self.window;
+        }
+    }
+
+    fn on_congestion_event(&mut self, sent_at: Instant, now: Instant) {
+        if self.in_recovery(sent_at) {
+            return;
+        }
+
+        self.recovery_start = Some(now);
+        self.ssthresh = (self.window / 2).max(MIN_WINDOW);
+        self.window = self.ssthresh;
+    }
+
+    fn on_timeout(&mut self, now: Instant) {
+        self.recovery_start = Some(now);
+        self.ssthresh = (self.window / 2).max(MIN_WINDOW);
+        self.window = MIN_WINDOW;
+    }
+}
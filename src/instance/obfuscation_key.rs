@@ -0,0 +1,116 @@
+use std::{
+    fmt::{ Formatter, Debug, Error },
+    convert::TryInto,
+};
+use openssl::{
+    symm::{ Cipher, encrypt },
+    error::ErrorStack,
+};
+use rand::{
+    prelude::ThreadRng,
+    Rng,
+};
+use crate::{
+    SharedMacSecret,
+    hkdf::{ self, HASH_SIZE },
+    instance::packet::MAGIC,
+};
+
+const OBFUSCATION_KEY_LABEL: &[u8] = b"chat-test obfuscation";
+// Random padding appended to every packet, as far as the path MTU allows
+pub const MAX_PADDING: usize = 64;
+const PADDING_LENGTH_SIZE: usize = 2;
+// ChaCha20 in OpenSSL takes a 32 bit block counter followed by the nonce
+const NONCE_SIZE: usize = 12;
+const HASH_OFFSET: usize = MAGIC.len() + 1;
+const HASH_END: usize = HASH_OFFSET + 32;
+// How much longer an obfuscated packet is than the packet it encodes, before the random padding
+pub const OVERHEAD: usize = NONCE_SIZE + PADDING_LENGTH_SIZE - MAGIC.len();
+
+// Makes packets look like random bytes to anyone without the shared MAC secret. The routing
+// identifier and the MAC already do, so they stay where they are and everything in between is
+// encrypted with ChaCha20 under a random nonce. The MAC would make a poor nonce, a retransmitted
+// packet has the same one and both ciphertexts would give away that they carry the same packet.
+// The magic is replaced by the length of the random padding that follows the body:
+//
+//   hash      32 bytes
+//   nonce     12 bytes  random for every datagram
+//   padding   2 bytes   \
+//   version   1 byte     |  encrypted
+//   body      rest       |
+//   padding   random    /
+//   mac       32 bytes  over the packet as it is encoded without obfuscation
+//
+// Public keys only ever appear inside the encrypted part, the peer holds the secret before the
+// first packet, so they need no encoding of their own to look random.
+pub struct ObfuscationKey ([u8; 32]);
+
+impl ObfuscationKey {
+    pub fn new(shared_mac_secret: &SharedMacSecret) -> Result<Self, ErrorStack> {
+        let mut key = [0u8; 32];
+        hkdf::expand(shared_mac_secret.as_ref(), OBFUSCATION_KEY_LABEL, &mut key)?;
+
+        Ok(ObfuscationKey(key))
+    }
+
+    fn apply(&self, nonce: &[u8], data: &[u8]) -> Result<Vec<u8>, ErrorStack> {
+        let mut iv = [0u8; 4 + NONCE_SIZE];
+        iv[4..].copy_from_slice(nonce);
+
+        encrypt(Cipher::chacha20(), &self.0, Some(&iv), data)
+    }
+
+    // Takes an encoded packet and up to `room` bytes of random padding to add on top of `OVERHEAD`
+    pub fn obfuscate(&self, rng: &mut ThreadRng, packet: &[u8], room: usize) -> Result<Vec<u8>, ErrorStack> {
+        let (authenticated, mac) = packet.split_at(packet.len() - HASH_SIZE);
+        let padding = rng.gen_range(0, MAX_PADDING.min(room) + 1);
+
+        let mut plaintext = Vec::with_capacity(packet.len() + padding);
+        plaintext.extend_from_slice(&(padding as u16).to_be_bytes());
+        plaintext.push(authenticated[MAGIC.len()]);
+        plaintext.extend_from_slice(&authenticated[HASH_END..]);
+        let body = plaintext.len();
+        plaintext.resize(body + padding, 0);
+        rng.fill(&mut plaintext[body..]);
+
+        let mut nonce = [0u8; NONCE_SIZE];
+        rng.fill(&mut nonce);
+
+        let mut bytes = authenticated[HASH_OFFSET..HASH_END].to_vec();
+        bytes.extend_from_slice(&nonce);
+        bytes.extend(self.apply(&nonce, &plaintext)?);
+        bytes.extend_from_slice(mac);
+
+        Ok(bytes)
+    }
+
+    // Restores the packet as it was encoded, the MAC is checked once it is decoded
+    pub fn reveal(&self, bytes: &[u8]) -> Option<Vec<u8>> {
+        if bytes.len() < HASH_END - HASH_OFFSET + NONCE_SIZE + PADDING_LENGTH_SIZE + 1 + HASH_SIZE {
+            return None;
+        }
+
+        let (hash, rest) = bytes.split_at(HASH_END - HASH_OFFSET);
+        let (nonce, rest) = rest.split_at(NONCE_SIZE);
+        let (ciphertext, mac) = rest.split_at(rest.len() - HASH_SIZE);
+        let plaintext = self.apply(nonce, ciphertext).ok()?;
+
+        let padding = u16::from_be_bytes(plaintext[..PADDING_LENGTH_SIZE].try_into().unwrap()) as usize;
+        let body = plaintext[PADDING_LENGTH_SIZE + 1..].len().checked_sub(padding)?;
+
+        let mut packet = Vec::with_capacity(bytes.len() + MAGIC.len());
+        packet.extend_from_slice(&MAGIC);
+        packet.push(plaintext[PADDING_LENGTH_SIZE]);
+        packet.extend_from_slice(hash);
+        packet.extend_from_slice(&plaintext[PADDING_LENGTH_SIZE + 1..][..body]);
+        packet.extend_from_slice(mac);
+
+        Some(packet)
+    }
+}
+
+impl Debug for ObfuscationKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.write_str("\"<obfuscation key>\"")
+    }
+}
@@ -0,0 +1,24 @@
+use std::time::{ Duration, Instant };
+
+// Sends a little faster than the window per round trip so the pacer itself is not the bottleneck
+const PACING_GAIN: f64 = 1.25;
+
+// Spreads reliable messages over the round trip instead of sending the whole window at once
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Pacer {
+    pub next_send_at: Option<Instant>,
+}
+
+impl Pacer {
+    pub fn can_send(&self, now: Instant) -> bool {
+        self.next_send_at.is_none_or(|next_send_at| next_send_at <= now)
+    }
+
+    // Without an RTT sample there is nothing to pace by
+    pub fn on_sent(&mut self, bytes: usize, window: usize, srtt: Option<Duration>, now: Instant) {
+        self.next_send_at = srtt.map(|srtt| {
+            let interval = srtt.mul_f64(bytes as f64 / (window as f64 * PACING_GAIN));
+            self.next_send_at.map_or(now, |next_send_at| next_send_at.max(now)) + interval
+        });
+    }
+}
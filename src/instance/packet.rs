@@ -1,18 +1,254 @@
-use serde::{ Serialize, Deserialize };
+use std::convert::TryInto;
+use openssl::memcmp;
 use crate::{
     x25519IDHash,
-    instance::EphemeralBlob,
+    SharedMacSecret,
+    hkdf::HASH_SIZE,
+    instance::{ Sealed, PacketError, cookie_checker::COOKIE_SIZE },
 };
 
-#[derive(Serialize, Deserialize)]
+// Wire format, integers are big endian
+//
+//   magic     4 bytes   "CHAT"
+//   version   1 byte
+//   hash      32 bytes  routing identifier of the sender for the current epoch
+//   body      rest      depends on the version
+//   mac       32 bytes  HMAC with the shared MAC secret over everything before it
+//
+// Magic, version, hash and the trailing MAC are kept by every future version, so a peer can
+// always authenticate a packet and tell which version it uses even if it cannot read the body.
+//
+// Version 0 is reserved for version negotiation, its body lists the supported versions with
+// one byte each. In version 1 the body starts with a type byte:
+//
+//   0  Handshake       message index (1 byte), cookie MAC (16 bytes), Noise message
+//   1  Message         \
+//   2  Rekey            |
+//   3  PathChallenge    |
+//   4  PathResponse     |  sealed: generation (4 bytes), counter (8 bytes), ciphertext
+//   5  Keepalive        |
+//   6  Ack              |
+//   7  Fragment         |
+//   8  PmtuProbe        |
+//   9  PmtuAck         /
+//  10  HandshakeFinished  MAC over the handshake transcript (32 bytes)
+//  11  CookieReply        nonce (32 bytes), encrypted cookie
+//
+// Instances set up for obfuscation send packets in this format through `ObfuscationKey`.
+pub const MAGIC: [u8; 4] = *b"CHAT";
+pub const VERSION: u8 = 1;
+pub const SUPPORTED_VERSIONS: &[u8] = &[VERSION];
+const VERSION_NEGOTIATION: u8 = 0;
+const HEADER_SIZE: usize = 4 + 1 + 32;
+const SEALED_HEADER_SIZE: usize = 4 + 8;
+const COOKIE_NONCE_SIZE: usize = 32;
+
 pub struct Packet {
     pub hash: x25519IDHash,
     pub data: Data,
+    pub mac: [u8; HASH_SIZE],
+}
+
+impl Packet {
+    fn encode_unauthenticated(hash: &x25519IDHash, data: &Data) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + 64);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(data.version());
+        bytes.extend_from_slice(hash.as_ref());
+        data.encode(&mut bytes);
+
+        bytes
+    }
+
+    pub fn new(hash: x25519IDHash, shared_mac_secret: &SharedMacSecret, data: Data) -> Self {
+        let mac = shared_mac_secret.mac(&[&Packet::encode_unauthenticated(&hash, &data)]).unwrap();
+
+        Packet {
+            hash,
+            data,
+            mac,
+        }
+    }
+
+    pub fn verify(&self, shared_mac_secret: &SharedMacSecret) -> bool {
+        memcmp::eq(&self.mac, &shared_mac_secret.mac(&[&Packet::encode_unauthenticated(&self.hash, &self.data)]).unwrap())
+    }
+
+    // Checks the MAC of an encoded packet, which also works for versions we cannot decode
+    pub fn authenticates(bytes: &[u8], shared_mac_secret: &SharedMacSecret) -> bool {
+        if bytes.len() < HEADER_SIZE + HASH_SIZE {
+            return false;
+        }
+
+        let (authenticated, mac) = bytes.split_at(bytes.len() - HASH_SIZE);
+        memcmp::eq(mac, &shared_mac_secret.mac(&[authenticated]).unwrap())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Packet::encode_unauthenticated(&self.hash, &self.data);
+        bytes.extend_from_slice(&self.mac);
+
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, PacketError> {
+        if bytes.len() < HEADER_SIZE + HASH_SIZE {
+            return Err(PacketError::Truncated);
+        }
+        if bytes[..4] != MAGIC {
+            return Err(PacketError::BadMagic);
+        }
+
+        let hash = x25519IDHash::from(bytes[5..HEADER_SIZE].to_vec());
+        let body = &bytes[HEADER_SIZE..bytes.len() - HASH_SIZE];
+        let data = match bytes[4] {
+            VERSION_NEGOTIATION => Data::VersionNegotiation { versions: body.to_vec() },
+            VERSION => Data::decode(body)?,
+            version => return Err(PacketError::UnsupportedVersion { hash, version }),
+        };
+
+        Ok(Packet {
+            hash,
+            data,
+            mac: bytes[bytes.len() - HASH_SIZE..].try_into().unwrap(),
+        })
+    }
 }
 
-#[derive(Serialize, Deserialize)]
 pub enum Data {
     Handshake {
-        ephemeral_blob: EphemeralBlob
+        message_index: u8,
+        // All zeros unless the initiator holds a cookie from a busy responder
+        cookie_mac: [u8; COOKIE_SIZE],
+        message: Vec<u8>,
+    },
+    // Sent in reply to a packet with a version we do not support
+    VersionNegotiation {
+        versions: Vec<u8>,
+    },
+    // Proves that the sender derived the same keys from the same transcript
+    HandshakeFinished {
+        mac: [u8; HASH_SIZE],
+    },
+    // Sent instead of answering an initiation while busy
+    CookieReply {
+        nonce: [u8; COOKIE_NONCE_SIZE],
+        encrypted_cookie: Vec<u8>,
+    },
+    Message(Sealed),
+    Rekey(Sealed),
+    PathChallenge(Sealed),
+    PathResponse(Sealed),
+    Keepalive(Sealed),
+    Ack(Sealed),
+    Fragment(Sealed),
+    PmtuProbe(Sealed),
+    PmtuAck(Sealed),
+}
+
+impl Data {
+    fn version(&self) -> u8 {
+        match self {
+            Data::VersionNegotiation { .. } => VERSION_NEGOTIATION,
+            _ => VERSION,
+        }
+    }
+
+    fn sealed(&self) -> Option<(u8, &Sealed)> {
+        match self {
+            Data::Handshake { .. }
+            | Data::VersionNegotiation { .. }
+            | Data::HandshakeFinished { .. }
+            | Data::CookieReply { .. } => None,
+            Data::Message(sealed) => Some((1, sealed)),
+            Data::Rekey(sealed) => Some((2, sealed)),
+            Data::PathChallenge(sealed) => Some((3, sealed)),
+            Data::PathResponse(sealed) => Some((4, sealed)),
+            Data::Keepalive(sealed) => Some((5, sealed)),
+            Data::Ack(sealed) => Some((6, sealed)),
+            Data::Fragment(sealed) => Some((7, sealed)),
+            Data::PmtuProbe(sealed) => Some((8, sealed)),
+            Data::PmtuAck(sealed) => Some((9, sealed)),
+        }
+    }
+
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        match self {
+            Data::Handshake { message_index, cookie_mac, message } => {
+                bytes.push(0);
+                bytes.push(*message_index);
+                bytes.extend_from_slice(cookie_mac);
+                bytes.extend_from_slice(message);
+            },
+            Data::VersionNegotiation { versions } => bytes.extend_from_slice(versions),
+            Data::HandshakeFinished { mac } => {
+                bytes.push(10);
+                bytes.extend_from_slice(mac);
+            },
+            Data::CookieReply { nonce, encrypted_cookie } => {
+                bytes.push(11);
+                bytes.extend_from_slice(nonce);
+                bytes.extend_from_slice(encrypted_cookie);
+            },
+            _ => {
+                let (tag, sealed) = self.sealed().unwrap();
+                bytes.push(tag);
+                bytes.extend_from_slice(&sealed.generation.to_be_bytes());
+                bytes.extend_from_slice(&sealed.counter.to_be_bytes());
+                bytes.extend_from_slice(&sealed.ciphertext);
+            },
+        }
+    }
+
+    fn decode(body: &[u8]) -> Result<Self, PacketError> {
+        let (&tag, body) = body.split_first().ok_or(PacketError::Truncated)?;
+        if tag == 0 {
+            if body.len() < 1 + COOKIE_SIZE {
+                return Err(PacketError::Truncated);
+            }
+
+            return Ok(Data::Handshake {
+                message_index: body[0],
+                cookie_mac: body[1..1 + COOKIE_SIZE].try_into().unwrap(),
+                message: body[1 + COOKIE_SIZE..].to_vec(),
+            });
+        }
+        if tag == 10 {
+            return Ok(Data::HandshakeFinished {
+                mac: body.try_into().map_err(|_| PacketError::InvalidLength)?,
+            });
+        }
+        if tag == 11 {
+            if body.len() < COOKIE_NONCE_SIZE {
+                return Err(PacketError::Truncated);
+            }
+
+            return Ok(Data::CookieReply {
+                nonce: body[..COOKIE_NONCE_SIZE].try_into().unwrap(),
+                encrypted_cookie: body[COOKIE_NONCE_SIZE..].to_vec(),
+            });
+        }
+
+        let data: fn(Sealed) -> Data = match tag {
+            1 => Data::Message,
+            2 => Data::Rekey,
+            3 => Data::PathChallenge,
+            4 => Data::PathResponse,
+            5 => Data::Keepalive,
+            6 => Data::Ack,
+            7 => Data::Fragment,
+            8 => Data::PmtuProbe,
+            9 => Data::PmtuAck,
+            tag => return Err(PacketError::UnknownType(tag)),
+        };
+        if body.len() < SEALED_HEADER_SIZE {
+            return Err(PacketError::Truncated);
+        }
+
+        Ok(data(Sealed {
+            generation: u32::from_be_bytes(body[..4].try_into().unwrap()),
+            counter: u64::from_be_bytes(body[4..SEALED_HEADER_SIZE].try_into().unwrap()),
+            ciphertext: body[SEALED_HEADER_SIZE..].to_vec(),
+        }))
     }
-}
\ No newline at end of file
+}
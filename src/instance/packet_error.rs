@@ -0,0 +1,28 @@
+use std::fmt::{ Formatter, Display, Error };
+use crate::x25519IDHash;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PacketError {
+    Truncated,
+    BadMagic,
+    UnsupportedVersion {
+        hash: x25519IDHash,
+        version: u8,
+    },
+    UnknownType(u8),
+    InvalidLength,
+}
+
+impl Display for PacketError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            PacketError::Truncated => f.write_str("packet is truncated"),
+            PacketError::BadMagic => f.write_str("packet does not start with the protocol magic"),
+            PacketError::UnsupportedVersion { version, .. } => write!(f, "unsupported protocol version {}", version),
+            PacketError::UnknownType(tag) => write!(f, "unknown packet type {}", tag),
+            PacketError::InvalidLength => f.write_str("packet body has the wrong length"),
+        }
+    }
+}
+
+impl std::error::Error for PacketError {}
@@ -0,0 +1,76 @@
+use serde::{ Serialize, Deserialize };
+use rand::{
+    prelude::ThreadRng,
+    Rng,
+};
+
+// Marks where the padding starts, only zeros follow it
+const PADDING_MARKER: u8 = 0x80;
+
+// How sealed payloads are padded to hide their length. The padding is the same whatever the
+// policy, so the receiver removes it without knowing how the sender chose its length.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PaddingPolicy {
+    #[default]
+    None,
+    // Up to the next power of two
+    PowerOfTwo,
+    // Up to the next multiple of the block size
+    Block(u16),
+    // By a random number of bytes up to the given one
+    Random(u16),
+}
+
+impl PaddingPolicy {
+    // A peer that does not ask for padding goes along with the other one, when both ask for
+    // different policies the initiator's is used
+    pub fn negotiate(initiator: PaddingPolicy, responder: PaddingPolicy) -> Self {
+        match initiator {
+            PaddingPolicy::None => responder,
+            _ => initiator,
+        }
+    }
+
+    // Bytes added to every payload even when there is no room for more
+    pub fn overhead(self) -> usize {
+        match self {
+            PaddingPolicy::None => 0,
+            _ => 1,
+        }
+    }
+
+    // Payloads are padded to at most `limit` bytes, the marker is added even beyond it
+    pub fn pad(self, rng: &mut ThreadRng, plaintext: &[u8], limit: usize) -> Vec<u8> {
+        let length = plaintext.len() + self.overhead();
+        let padded_length = match self {
+            PaddingPolicy::None => return plaintext.to_vec(),
+            PaddingPolicy::PowerOfTwo => length.next_power_of_two(),
+            PaddingPolicy::Block(size) => {
+                let size = size.max(1) as usize;
+                length.div_ceil(size) * size
+            },
+            PaddingPolicy::Random(max) => length + rng.gen_range(0, max as usize + 1),
+        }.min(limit).max(length);
+
+        let mut padded = Vec::with_capacity(padded_length);
+        padded.extend_from_slice(plaintext);
+        padded.push(PADDING_MARKER);
+        padded.resize(padded_length, 0);
+
+        padded
+    }
+
+    pub fn unpad(self, mut padded: Vec<u8>) -> Option<Vec<u8>> {
+        if self == PaddingPolicy::None {
+            return Some(padded);
+        }
+
+        let marker = padded.iter().rposition(|byte| *byte != 0)?;
+        if padded[marker] != PADDING_MARKER {
+            return None;
+        }
+        padded.truncate(marker);
+
+        Some(padded)
+    }
+}
@@ -0,0 +1,100 @@
+use std::time::{ Duration, Instant };
+
+// Assumed to work on any path, as in QUIC
+pub const BASE_PLPMTU: usize = 1200;
+// Ethernet MTU minus the IPv4 and UDP headers
+pub const MAX_PLPMTU: usize = 1472;
+// Searching stops once the remaining range is this small
+const SEARCH_GRANULARITY: usize = 16;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_PROBES: u8 = 3;
+// The path may have grown in the meantime, search again after this long
+const RAISE_INTERVAL: Duration = Duration::from_secs(600);
+
+// Datagram packetization layer PMTU discovery as described in RFC 8899. Padded probes search
+// between the confirmed size and the largest one that has not failed yet.
+#[derive(Debug, Copy, Clone)]
+pub struct PathMtu {
+    // Largest datagram known to get through
+    pub plpmtu: usize,
+    pub search_high: usize,
+    pub probe_size: Option<usize>,
+    pub probe_sent_at: Option<Instant>,
+    pub probe_attempts: u8,
+    // Set once the search is complete
+    pub searched_at: Option<Instant>,
+}
+
+impl Default for PathMtu {
+    fn default() -> Self {
+        PathMtu {
+            plpmtu: BASE_PLPMTU,
+            search_high: MAX_PLPMTU,
+            probe_size: None,
+            probe_sent_at: None,
+            probe_attempts: 0,
+            searched_at: None,
+        }
+    }
+}
+
+impl PathMtu {
+    // Returns the size of the probe to send now, if any
+    pub fn next_probe(&mut self, now: Instant) -> Option<usize> {
+        if let Some(searched_at) = self.searched_at {
+            if now.duration_since(searched_at) < RAISE_INTERVAL {
+                return None;
+            }
+            self.search_high = MAX_PLPMTU;
+            self.searched_at = None;
+        }
+
+        if let (Some(size), Some(sent_at)) = (self.probe_size, self.probe_sent_at) {
+            if now.duration_since(sent_at) < PROBE_TIMEOUT {
+                return None;
+            }
+            if self.probe_attempts < MAX_PROBES {
+                self.probe_sent_at = Some(now);
+                self.probe_attempts += 1;
+                return Some(size);
+            }
+
+            // Nothing this large gets through
+            self.search_high = size - 1;
+            self.probe_size = None;
+        }
+
+        if self.search_high < self.plpmtu + SEARCH_GRANULARITY {
+            self.searched_at = Some(now);
+            return None;
+        }
+
+        let size = (self.plpmtu + self.search_high).div_ceil(2);
+        self.probe_size = Some(size);
+        self.probe_sent_at = Some(now);
+        self.probe_attempts = 1;
+
+        Some(size)
+    }
+
+    pub fn acknowledged(&mut self, size: usize) {
+        if self.probe_size == Some(size) {
+            self.plpmtu = size;
+            self.probe_size = None;
+            self.probe_sent_at = None;
+        }
+    }
+
+    // Full-sized packets stopped getting through, start over from the base size
+    pub fn black_hole(&mut self) {
+        *self = PathMtu::default();
+    }
+
+    pub fn next_timeout(&self) -> Option<Instant> {
+        match (self.searched_at, self.probe_sent_at) {
+            (Some(searched_at), _) => Some(searched_at + RAISE_INTERVAL),
+            (None, Some(sent_at)) => Some(sent_at + PROBE_TIMEOUT),
+            (None, None) => None,
+        }
+    }
+}
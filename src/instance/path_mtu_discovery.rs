@@ -0,0 +1,117 @@
+use std::{
+    net::UdpSocket,
+    time::Instant,
+};
+use crate::instance::{ Instance, Connection, connection, Data, Capability, ProbePayload, PMTU_PROBE_LABEL, PMTU_ACK_LABEL };
+
+impl Instance {
+    pub(super) fn probe_path_mtu(&self, socket: &UdpSocket, connection: &mut Connection, now: Instant) {
+        let endpoint = match (&connection.state, connection.endpoint) {
+            (connection::State::Established { session, capabilities, .. }, Some(endpoint))
+                if session.confirmed && capabilities.contains(Capability::PathMtuDiscovery) => endpoint,
+            _ => return,
+        };
+
+        if let Some(size) = connection.path_mtu.next_probe(now) {
+            let probe = bincode::serialize(&ProbePayload::padded(size, connection.padding_policy().overhead() + connection.obfuscation_overhead())).unwrap();
+            self.send_sealed(socket, connection, PMTU_PROBE_LABEL, &probe, Data::PmtuProbe, endpoint);
+        }
+    }
+
+    pub(super) fn handle_probe(&self, socket: &UdpSocket, connection: &mut Connection, plaintext: &[u8]) {
+        let endpoint = match connection.endpoint {
+            Some(endpoint) => endpoint,
+            None => return,
+        };
+
+        match bincode::deserialize::<ProbePayload>(plaintext) {
+            Ok(probe) => {
+                let ack = bincode::serialize(&ProbePayload::ack(probe.size)).unwrap();
+                self.send_sealed(socket, connection, PMTU_ACK_LABEL, &ack, Data::PmtuAck, endpoint);
+            },
+            Err(_) => println!("Received malformed probe from {}", connection.remote_x25519_id_hash),
+        }
+    }
+
+    pub(super) fn handle_probe_ack(&self, connection: &mut Connection, plaintext: &[u8]) {
+        match bincode::deserialize::<ProbePayload>(plaintext) {
+            Ok(ack) => connection.path_mtu.acknowledged(ack.size as usize),
+            Err(_) => println!("Received malformed probe acknowledgement from {}", connection.remote_x25519_id_hash),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::UdpSocket,
+        time::{ Duration, Instant },
+    };
+    use rand::{ thread_rng, RngCore };
+    use crate::{
+        x25519::{ PrivateKey, PublicKey },
+        SharedMacSecret,
+        instance::{
+            Instance, InstanceBuilder, connection, Capabilities, PaddingPolicy, ObfuscationKey, PathMtu, path_mtu::BASE_PLPMTU,
+            test_util::{ connection, pair },
+        },
+    };
+
+    #[test]
+    fn path_mtu_search() {
+        let mut path_mtu = PathMtu::default();
+        let mut now = Instant::now();
+        let mut probes = 0;
+
+        // Anything up to 1400 bytes gets through
+        while let Some(size) = path_mtu.next_probe(now) {
+            probes += 1;
+            if size <= 1400 {
+                path_mtu.acknowledged(size);
+            } else {
+                now = path_mtu.next_timeout().unwrap();
+            }
+        }
+
+        assert!(path_mtu.plpmtu <= 1400 && path_mtu.plpmtu > 1400 - 16);
+        assert!(probes < 16);
+        assert!(path_mtu.next_timeout().unwrap() > now);
+
+        path_mtu.black_hole();
+        assert_eq!(path_mtu.plpmtu, BASE_PLPMTU);
+    }
+
+    #[test]
+    fn probes_are_sent_at_the_size_they_test() {
+        let mut rng = thread_rng();
+        let mut secret = [0u8; 128];
+        rng.fill_bytes(&mut secret);
+        let (instance, _channels) = Instance::new(InstanceBuilder::new(), PrivateKey::new(&secret).unwrap(), PublicKey::new(&secret).unwrap());
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        peer.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let endpoint = peer.local_addr().unwrap();
+
+        // Neither the padding marker nor obfuscation may change the size on the wire
+        for obfuscation in &[false, true] {
+            for padding_policy in &[PaddingPolicy::None, PaddingPolicy::PowerOfTwo, PaddingPolicy::Random(32)] {
+                let (mut session, _) = pair();
+                session.confirmed = true;
+                let shared_mac_secret = SharedMacSecret::new(&mut rng);
+                let mut connection = connection(shared_mac_secret);
+                connection.state = connection::State::Established { session: Box::new(session), capabilities: Capabilities::all(), padding_policy: *padding_policy };
+                connection.set_endpoint(endpoint);
+                connection.amplification.validate(endpoint);
+                if *obfuscation {
+                    connection.obfuscation = Some(ObfuscationKey::new(&shared_mac_secret).unwrap());
+                }
+
+                instance.probe_path_mtu(&socket, &mut connection, Instant::now());
+
+                let mut buffer = [0u8; 2048];
+                assert_eq!(peer.recv(&mut buffer).unwrap(), connection.path_mtu.probe_size.unwrap());
+            }
+        }
+    }
+}
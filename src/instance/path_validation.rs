@@ -0,0 +1,27 @@
+use std::{
+    net::SocketAddr,
+    time::Instant,
+};
+
+pub const PATH_CHALLENGE_SIZE: usize = 8;
+
+// An address the peer appears to have moved to, only used for outbound traffic once it echoed
+// the challenge back
+#[derive(Debug, Copy, Clone)]
+pub struct PathValidation {
+    pub endpoint: SocketAddr,
+    pub challenge: [u8; PATH_CHALLENGE_SIZE],
+    pub sent_at: Option<Instant>,
+    pub attempts: u8,
+}
+
+impl PathValidation {
+    pub fn new(endpoint: SocketAddr) -> Self {
+        PathValidation {
+            endpoint,
+            challenge: [0u8; PATH_CHALLENGE_SIZE],
+            sent_at: None,
+            attempts: 0,
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use serde::{ Serialize, Deserialize };
+use crate::instance::SEALED_PACKET_OVERHEAD;
+
+// Carried inside `Data::PmtuProbe`, padded so the whole datagram is `size` bytes, and echoed
+// back without the padding inside `Data::PmtuAck`. The padding marker and obfuscation add
+// `overhead` bytes on top, probes leave room for them so they still test exactly `size` bytes.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProbePayload {
+    pub size: u32,
+    pub padding: Vec<u8>,
+}
+
+// The size and the length of the padding
+const PROBE_PAYLOAD_OVERHEAD: usize = 4 + 8;
+
+impl ProbePayload {
+    pub fn padded(size: usize, overhead: usize) -> Self {
+        ProbePayload {
+            size: size as u32,
+            padding: vec![0u8; size.saturating_sub(SEALED_PACKET_OVERHEAD + PROBE_PAYLOAD_OVERHEAD + overhead)],
+        }
+    }
+
+    pub fn ack(size: u32) -> Self {
+        ProbePayload {
+            size,
+            padding: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::{
+    net::IpAddr,
+    time::{ Duration, Instant },
+    collections::HashMap,
+};
+
+// Every address may start 20 handshakes per second with bursts of up to 5
+const PACKET_COST: Duration = Duration::from_millis(50);
+const BUCKET_SIZE: Duration = Duration::from_millis(5 * 50);
+// Addresses tracked at once, beyond that new ones are refused until old buckets fill up again
+const MAX_ENTRIES: usize = 4096;
+
+#[derive(Debug, Copy, Clone)]
+struct Bucket {
+    tokens: Duration,
+    updated_at: Instant,
+}
+
+// Token bucket per source IP address for handshake initiations
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: HashMap<IpAddr, Bucket>,
+}
+
+impl RateLimiter {
+    pub fn allow(&mut self, address: IpAddr, now: Instant) -> bool {
+        if !self.buckets.contains_key(&address) && self.buckets.len() >= MAX_ENTRIES {
+            self.expire(now);
+            if self.buckets.len() >= MAX_ENTRIES {
+                return false;
+            }
+        }
+
+        let bucket = self.buckets.entry(address).or_insert(Bucket {
+            tokens: BUCKET_SIZE,
+            updated_at: now,
+        });
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated_at)).min(BUCKET_SIZE);
+        bucket.updated_at = now;
+
+        if bucket.tokens < PACKET_COST {
+            return false;
+        }
+        bucket.tokens -= PACKET_COST;
+        true
+    }
+
+    // A full bucket is the same as no bucket at all
+    pub fn expire(&mut self, now: Instant) {
+        self.buckets.retain(|_, bucket| bucket.tokens + now.duration_since(bucket.updated_at) < BUCKET_SIZE);
+    }
+}
@@ -0,0 +1,91 @@
+use std::{
+    time::{ Duration, Instant },
+    collections::HashMap,
+};
+use crate::instance::{ FragmentPayload, ReassemblyError };
+
+pub const MAX_MESSAGE_SIZE: usize = 1 << 20;
+pub const MAX_FRAGMENTS: u16 = 1024;
+// Partial messages are given up on after this long
+pub const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(10);
+// Bounds what a peer can make us hold on to with fragments it never completes
+const MAX_BUFFERED_BYTES: usize = 4 * MAX_MESSAGE_SIZE;
+const MAX_PARTIAL_MESSAGES: usize = 64;
+
+#[derive(Debug, Clone)]
+struct Partial {
+    fragments: Vec<Option<Vec<u8>>>,
+    received: u16,
+    bytes: usize,
+    started_at: Instant,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Reassembly {
+    partial: HashMap<u64, Partial>,
+    buffered_bytes: usize,
+}
+
+impl Reassembly {
+    // Returns the message once its last fragment arrived
+    pub fn insert(&mut self, fragment: FragmentPayload, now: Instant) -> Result<Option<Vec<u8>>, ReassemblyError> {
+        if fragment.count == 0 || fragment.count > MAX_FRAGMENTS || fragment.index >= fragment.count {
+            return Err(ReassemblyError::InvalidFragment);
+        }
+        if !self.partial.contains_key(&fragment.message_id) && self.partial.len() >= MAX_PARTIAL_MESSAGES {
+            return Err(ReassemblyError::OutOfMemory);
+        }
+        if self.buffered_bytes + fragment.data.len() > MAX_BUFFERED_BYTES {
+            return Err(ReassemblyError::OutOfMemory);
+        }
+
+        let partial = self.partial.entry(fragment.message_id).or_insert_with(|| Partial {
+            fragments: vec![None; fragment.count as usize],
+            received: 0,
+            bytes: 0,
+            started_at: now,
+        });
+        if partial.fragments.len() != fragment.count as usize {
+            return Err(ReassemblyError::InvalidFragment);
+        }
+        if partial.fragments[fragment.index as usize].is_some() {
+            return Ok(None);
+        }
+        if partial.bytes + fragment.data.len() > MAX_MESSAGE_SIZE {
+            let bytes = partial.bytes;
+            self.partial.remove(&fragment.message_id);
+            self.buffered_bytes -= bytes;
+            return Err(ReassemblyError::TooLarge);
+        }
+
+        partial.bytes += fragment.data.len();
+        partial.received += 1;
+        self.buffered_bytes += fragment.data.len();
+        partial.fragments[fragment.index as usize] = Some(fragment.data);
+
+        if partial.received < fragment.count {
+            return Ok(None);
+        }
+
+        let partial = self.partial.remove(&fragment.message_id).unwrap();
+        self.buffered_bytes -= partial.bytes;
+
+        Ok(Some(partial.fragments.into_iter().flatten().flatten().collect()))
+    }
+
+    pub fn expire(&mut self, now: Instant) {
+        let buffered_bytes = &mut self.buffered_bytes;
+        self.partial.retain(|_, partial| {
+            let keep = now.duration_since(partial.started_at) < REASSEMBLY_TIMEOUT;
+            if !keep {
+                *buffered_bytes -= partial.bytes;
+            }
+
+            keep
+        });
+    }
+
+    pub fn next_timeout(&self) -> Option<Instant> {
+        self.partial.values().map(|partial| partial.started_at + REASSEMBLY_TIMEOUT).min()
+    }
+}
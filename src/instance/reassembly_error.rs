@@ -0,0 +1,20 @@
+use std::fmt::{ Formatter, Display, Error };
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReassemblyError {
+    InvalidFragment,
+    TooLarge,
+    OutOfMemory,
+}
+
+impl Display for ReassemblyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            ReassemblyError::InvalidFragment => f.write_str("fragment does not match its message"),
+            ReassemblyError::TooLarge => f.write_str("message exceeds the size limit"),
+            ReassemblyError::OutOfMemory => f.write_str("too many partial messages buffered"),
+        }
+    }
+}
+
+impl std::error::Error for ReassemblyError {}
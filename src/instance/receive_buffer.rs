@@ -0,0 +1,54 @@
+use std::collections::BTreeMap;
+use crate::instance::AckPayload;
+
+// How far ahead of the next expected message the peer may get
+pub const RECEIVE_WINDOW: u64 = 1024;
+const MAX_ACK_RANGES: usize = 16;
+
+// Reliable messages that arrived ahead of one that is still missing
+#[derive(Debug, Clone, Default)]
+pub struct ReceiveBuffer {
+    pub next_expected: u64,
+    pub out_of_order: BTreeMap<u64, Vec<u8>>,
+}
+
+impl ReceiveBuffer {
+    pub fn accepts(&self, sequence: u64) -> bool {
+        sequence >= self.next_expected
+            && sequence - self.next_expected < RECEIVE_WINDOW
+            && !self.out_of_order.contains_key(&sequence)
+    }
+
+    // Returns the messages that can be handed over now, in order
+    pub fn insert(&mut self, sequence: u64, plaintext: Vec<u8>) -> Vec<Vec<u8>> {
+        self.out_of_order.insert(sequence, plaintext);
+
+        let mut deliverable = Vec::new();
+        while let Some(plaintext) = self.out_of_order.remove(&self.next_expected) {
+            deliverable.push(plaintext);
+            self.next_expected += 1;
+        }
+
+        deliverable
+    }
+
+    pub fn ack(&self) -> AckPayload {
+        let mut ranges: Vec<(u64, u64)> = Vec::new();
+        for sequence in self.out_of_order.keys().copied() {
+            match ranges.last_mut() {
+                Some((_, end)) if *end == sequence => *end += 1,
+                _ => {
+                    if ranges.len() == MAX_ACK_RANGES {
+                        break;
+                    }
+                    ranges.push((sequence, sequence + 1));
+                },
+            }
+        }
+
+        AckPayload {
+            cumulative: self.next_expected,
+            ranges,
+        }
+    }
+}
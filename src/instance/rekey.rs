@@ -0,0 +1,192 @@
+use std::{
+    net::UdpSocket,
+    time::Instant,
+};
+use rand::prelude::ThreadRng;
+use openssl::error::ErrorStack;
+use crate::{
+    x25519::PublicKey,
+    instance::{
+        Instance, Connection, connection, Data, RekeyPayload, Capabilities, PaddingPolicy, DisconnectReason,
+        REKEY_LABEL, KEEPALIVE_LABEL, REKEY_RETRY_INTERVAL,
+    },
+};
+
+impl Instance {
+    pub(super) fn send_keepalive(&self, socket: &UdpSocket, connection: &mut Connection) {
+        if let Some(endpoint) = connection.endpoint {
+            self.send_sealed(socket, connection, KEEPALIVE_LABEL, &[], Data::Keepalive, endpoint);
+        }
+    }
+
+    fn send_rekey(&self, socket: &UdpSocket, connection: &mut Connection, payload: RekeyPayload) {
+        if let Some(endpoint) = connection.endpoint {
+            let plaintext = bincode::serialize(&payload).unwrap();
+            self.send_sealed(socket, connection, REKEY_LABEL, &plaintext, Data::Rekey, endpoint);
+        }
+    }
+
+    pub(super) fn handle_rekey(&self, socket: &UdpSocket, connection: &mut Connection, payload: RekeyPayload) {
+        let session = match &mut connection.state {
+            connection::State::Established { session, .. } => session,
+            _ => return,
+        };
+
+        if payload.acknowledgement {
+            // Opening a packet of the next generation already switched over, this only matters when
+            // the acknowledgement was sealed with the current one
+            if payload.generation == session.generation + 1 && session.next_session_keys.is_some() {
+                if let Err(error) = session.advance() {
+                    let remote_public_key = session.remote_public_key;
+                    self.rekey_failed(connection, remote_public_key, error);
+                }
+            }
+            return;
+        }
+
+        if payload.generation == session.generation + 1 {
+            // Also covers both sides asking at the same time, they derive the same keys either way
+            if let Err(error) = session.advance() {
+                let remote_public_key = session.remote_public_key;
+                self.rekey_failed(connection, remote_public_key, error);
+                return;
+            }
+        } else if payload.generation != session.generation {
+            // Stale request, a repeated one for the current generation means our acknowledgement got lost
+            return;
+        }
+
+        let generation = session.generation;
+        self.send_rekey(socket, connection, RekeyPayload {
+            generation,
+            acknowledgement: true,
+        });
+    }
+
+    fn rekey_failed(&self, connection: &mut Connection, remote_public_key: PublicKey, error: ErrorStack) {
+        println!("Could not rekey the session with {}: {}", connection.remote_x25519_id_hash, error);
+
+        self.disconnect(connection, remote_public_key, DisconnectReason::RekeyFailed);
+    }
+
+    pub(super) fn maintain_session(&self, socket: &UdpSocket, rng: &mut ThreadRng, connection: &mut Connection, now: Instant) {
+        let session = match &mut connection.state {
+            connection::State::Established { session, .. } => session,
+            _ => return,
+        };
+        session.reassembly.expire(now);
+
+        if self.rekey_policy.session_expired(session, now) {
+            if let Some(endpoint) = connection.endpoint {
+                println!("Session with {} expired, starting a new handshake", connection.remote_x25519_id_hash);
+
+                connection.state = connection::State::Pending {
+                    remote_public_key: Some(session.remote_public_key),
+                    handshake_state: None,
+                    local_ratchet_secret: None,
+                    remote_ratchet_public_key: None,
+                    remote_capabilities: Capabilities::default(),
+                    remote_padding_policy: PaddingPolicy::default(),
+                };
+                self.start_handshake(socket, rng, connection, endpoint, now);
+            }
+            return;
+        }
+
+        if self.keepalive_policy.idle(session, now) {
+            let remote_public_key = session.remote_public_key;
+            self.disconnect(connection, remote_public_key, DisconnectReason::IdleTimeout);
+            return;
+        }
+
+        let retry = match session.rekey_requested_at {
+            Some(requested_at) => now.duration_since(requested_at) >= REKEY_RETRY_INTERVAL,
+            None => self.rekey_policy.rekey_due(session, now),
+        };
+        if retry {
+            let generation = match session.request_rekey(now) {
+                Ok(generation) => generation,
+                Err(error) => {
+                    let remote_public_key = session.remote_public_key;
+                    self.rekey_failed(connection, remote_public_key, error);
+                    return;
+                },
+            };
+            self.send_rekey(socket, connection, RekeyPayload {
+                generation,
+                acknowledgement: false,
+            });
+        } else if self.keepalive_policy.keepalive_due(session, now) {
+            self.send_keepalive(socket, connection);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+    use crate::instance::{ KeepalivePolicy, test_util::pair };
+
+    #[test]
+    fn rekey_keeps_in_flight_messages() {
+        let (mut alice, mut bob) = pair();
+
+        let in_flight = alice.seal(b"message", b"", b"sent before the rekey").unwrap();
+        assert_eq!(alice.sent_messages, 1);
+
+        // Alice asks, Bob switches right away and acknowledges with the new keys
+        let generation = alice.request_rekey(Instant::now()).unwrap();
+        assert_eq!(generation, 1);
+        bob.advance().unwrap();
+        let acknowledgement = bob.seal(b"rekey", b"", b"acknowledgement").unwrap();
+        assert_eq!(acknowledgement.generation, 1);
+
+        assert_eq!(alice.open(b"rekey", b"", &acknowledgement).unwrap(), b"acknowledgement");
+        assert_eq!(alice.generation, 1);
+        assert_eq!(alice.sent_messages, 0);
+        assert!(alice.next_session_keys.is_none());
+
+        // Sealed with generation 0 but delivered after both sides moved on
+        assert_eq!(bob.open(b"message", b"", &in_flight).unwrap(), b"sent before the rekey");
+
+        let sealed = alice.seal(b"message", b"", b"after the rekey").unwrap();
+        assert_eq!(bob.open(b"message", b"", &sealed).unwrap(), b"after the rekey");
+
+        // Once the next rekey happens the oldest generation is gone
+        alice.advance().unwrap();
+        bob.advance().unwrap();
+        assert!(bob.open(b"message", b"", &in_flight).is_err());
+    }
+
+    #[test]
+    fn simultaneous_rekey() {
+        let (mut alice, mut bob) = pair();
+
+        alice.request_rekey(Instant::now()).unwrap();
+        bob.request_rekey(Instant::now()).unwrap();
+        alice.advance().unwrap();
+        bob.advance().unwrap();
+
+        let sealed = bob.seal(b"message", b"", b"hello").unwrap();
+        assert_eq!(alice.open(b"message", b"", &sealed).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn keepalives_and_idle_detection_follow_traffic() {
+        let (mut alice, mut bob) = pair();
+        let policy = KeepalivePolicy::default();
+        let later = Instant::now() + policy.idle_timeout;
+
+        assert!(policy.keepalive_due(&alice, later));
+        assert!(policy.idle(&bob, later));
+
+        alice.last_sent_at -= policy.idle_timeout;
+        bob.last_received_at -= policy.idle_timeout;
+        let sealed = alice.seal(b"keepalive", &[], &[]).unwrap();
+        bob.open(b"keepalive", &[], &sealed).unwrap();
+        let now = Instant::now();
+
+        assert!(!policy.keepalive_due(&alice, now));
+        assert!(!policy.idle(&bob, now));
+    }
+}
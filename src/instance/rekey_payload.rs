@@ -0,0 +1,8 @@
+use serde::{ Serialize, Deserialize };
+
+// Carried inside `Data::Rekey`, asks the peer to move to `generation` or acknowledges that it did
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct RekeyPayload {
+    pub generation: u32,
+    pub acknowledgement: bool,
+}
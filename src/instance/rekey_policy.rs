@@ -0,0 +1,34 @@
+use std::time::{ Duration, Instant };
+use crate::instance::Session;
+
+#[derive(Debug, Copy, Clone)]
+pub struct RekeyPolicy {
+    pub after_messages: u64,
+    pub after_bytes: u64,
+    pub after_time: Duration,
+    // Rekeying only ratchets the traffic keys forward, fresh key material requires a new handshake
+    pub session_lifetime: Duration,
+}
+
+impl Default for RekeyPolicy {
+    fn default() -> Self {
+        RekeyPolicy {
+            after_messages: 1 << 16,
+            after_bytes: 1 << 30,
+            after_time: Duration::from_secs(120),
+            session_lifetime: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+impl RekeyPolicy {
+    pub fn rekey_due(&self, session: &Session, now: Instant) -> bool {
+        session.sent_messages >= self.after_messages
+            || session.sent_bytes >= self.after_bytes
+            || now.duration_since(session.rekeyed_at) >= self.after_time
+    }
+
+    pub fn session_expired(&self, session: &Session, now: Instant) -> bool {
+        now.duration_since(session.established_at) >= self.session_lifetime
+    }
+}
@@ -0,0 +1,464 @@
+use std::{
+    net::{ SocketAddr, UdpSocket },
+    time::Instant,
+};
+use rand::prelude::ThreadRng;
+use crate::{
+    x25519IDHash,
+    instance::{
+        Instance, Connection, connection, Data, Response, Delivery, Capability, DisconnectReason,
+        MessagePayload, AckPayload, FragmentPayload,
+        MESSAGE_LABEL, ACK_LABEL, FRAGMENT_LABEL, SEALED_PACKET_OVERHEAD, FRAGMENT_OVERHEAD, MAX_BODY_SIZE, BLACK_HOLE_TIMEOUTS,
+    },
+};
+
+impl Instance {
+    pub(super) fn handle_message(&self, socket: &UdpSocket, rng: &mut ThreadRng, connection: &mut Connection, x25519_id_hash: x25519IDHash, plaintext: &[u8]) {
+        let payload = match bincode::deserialize::<MessagePayload>(plaintext) {
+            Ok(payload) => payload,
+            Err(_) => {
+                println!("Received malformed message from {}", x25519_id_hash);
+                return;
+            },
+        };
+        let session = match &mut connection.state {
+            connection::State::Established { session, .. } => session,
+            _ => return,
+        };
+
+        // A retransmission of something we already have must not reach the ratchet again, the
+        // acknowledgement tells the peer what is still missing
+        if let Some(sequence) = payload.sequence {
+            if !session.receive_buffer.accepts(sequence) {
+                self.send_ack(socket, connection);
+                return;
+            }
+        }
+
+        let plaintext = match session.ratchet.decrypt(rng, &payload.header, payload.ciphertext.as_slice(), x25519_id_hash.as_ref()) {
+            Ok(plaintext) => plaintext,
+            Err(error) => {
+                println!("Failed to decrypt message from {}: {}", x25519_id_hash, error);
+
+                // The peer would retransmit a reliable message until the connection times out,
+                // every copy failing the same way, so the session is given up right away
+                if payload.sequence.is_some() {
+                    let remote_public_key = session.remote_public_key;
+                    self.disconnect(connection, remote_public_key, DisconnectReason::UndecryptableMessage);
+                }
+                return;
+            },
+        };
+        let deliverable = match payload.sequence {
+            Some(sequence) => session.receive_buffer.insert(sequence, plaintext),
+            None => vec![plaintext],
+        };
+
+        for plaintext in deliverable {
+            match String::from_utf8(plaintext) {
+                Ok(body) => {
+                    self.tx.send(Response::Message {
+                        x25519_id_hash,
+                        body
+                    }).unwrap();
+                },
+                Err(_) => println!("Received malformed message from {}", x25519_id_hash),
+            }
+        }
+
+        if payload.sequence.is_some() {
+            self.send_ack(socket, connection);
+        }
+    }
+
+    pub(super) fn handle_fragment(&self, connection: &mut Connection, fragment: FragmentPayload) -> Option<Vec<u8>> {
+        let session = match &mut connection.state {
+            connection::State::Established { session, .. } => session,
+            _ => return None,
+        };
+
+        match session.reassembly.insert(fragment, Instant::now()) {
+            Ok(message) => message,
+            Err(error) => {
+                connection.stats.dropped_fragments += 1;
+                println!("Dropped fragment from {}: {}", connection.remote_x25519_id_hash, error);
+                None
+            },
+        }
+    }
+
+    fn send_ack(&self, socket: &UdpSocket, connection: &mut Connection) {
+        let (ack, endpoint) = match (&connection.state, connection.endpoint) {
+            (connection::State::Established { session, .. }, Some(endpoint)) => (session.receive_buffer.ack(), endpoint),
+            _ => return,
+        };
+
+        self.send_sealed(socket, connection, ACK_LABEL, &bincode::serialize(&ack).unwrap(), Data::Ack, endpoint);
+    }
+
+    pub(super) fn handle_ack(&self, connection: &mut Connection, ack: &AckPayload) {
+        if let connection::State::Established { session, .. } = &mut connection.state {
+            let now = Instant::now();
+            let acknowledgement = session.send_buffer.acknowledge(ack, now);
+            if let Some(rtt) = acknowledgement.rtt {
+                session.rtt.sample(rtt);
+            }
+            if let Some(sent_at) = acknowledgement.sent_at {
+                session.congestion.on_ack(acknowledgement.bytes, sent_at, &session.rtt, now);
+            }
+        }
+    }
+
+    pub(super) fn retransmit_messages(&self, socket: &UdpSocket, connection: &mut Connection, now: Instant) {
+        let (session, endpoint) = match (&mut connection.state, connection.endpoint) {
+            (connection::State::Established { session, .. }, Some(endpoint)) => (session, endpoint),
+            _ => return,
+        };
+
+        let sequences: Vec<u64> = if session.send_buffer.timed_out(session.rtt.rto, now) {
+            // Nothing got through for a whole timeout, send everything again and wait longer next time
+            session.rtt.back_off();
+            session.congestion.on_timeout(now);
+            if session.rtt.timeouts == BLACK_HOLE_TIMEOUTS {
+                println!("Packets to {} keep getting lost, falling back to the base packet size", connection.remote_x25519_id_hash);
+                connection.path_mtu.black_hole();
+            }
+            session.send_buffer.in_flight.keys().copied().collect()
+        } else {
+            let lost = session.send_buffer.lost();
+            let sent_at = lost.iter().map(|sequence| session.send_buffer.in_flight[sequence].sent_at).max();
+            if let Some(sent_at) = sent_at {
+                session.congestion.on_congestion_event(sent_at, now);
+            }
+
+            lost
+        };
+
+        let plaintexts: Vec<Vec<u8>> = sequences.into_iter()
+            .filter_map(|sequence| session.send_buffer.retransmit(sequence, now))
+            .collect();
+        for plaintext in plaintexts {
+            self.send_message_payload(socket, connection, &plaintext, endpoint);
+        }
+    }
+
+    // Sends reliable messages as far as the congestion window and the pacer allow
+    pub(super) fn send_queued_messages(&self, socket: &UdpSocket, connection: &mut Connection, now: Instant) {
+        loop {
+            let (session, endpoint) = match (&mut connection.state, connection.endpoint) {
+                (connection::State::Established { session, .. }, Some(endpoint)) => (session, endpoint),
+                _ => return,
+            };
+            let size = match session.send_buffer.queued.front() {
+                Some((_, plaintext)) => plaintext.len(),
+                None => return,
+            };
+
+            // A message larger than the whole window still goes out once nothing else is in flight
+            let bytes_in_flight = session.send_buffer.bytes_in_flight();
+            if bytes_in_flight > 0 && bytes_in_flight + size > session.congestion.window() {
+                return;
+            }
+            if !session.pacer.can_send(now) {
+                return;
+            }
+
+            let (sequence, plaintext) = session.send_buffer.queued.pop_front().unwrap();
+            session.pacer.on_sent(size, session.congestion.window(), session.rtt.srtt, now);
+            session.send_buffer.sent(sequence, plaintext.clone(), now);
+            self.send_message_payload(socket, connection, &plaintext, endpoint);
+        }
+    }
+
+    // Splits payloads that do not fit into one datagram, every fragment is sealed on its own
+    fn send_message_payload(&self, socket: &UdpSocket, connection: &mut Connection, plaintext: &[u8], endpoint: SocketAddr) {
+        let room = connection.path_mtu.plpmtu - connection.padding_policy().overhead() - connection.obfuscation_overhead();
+        if plaintext.len() + SEALED_PACKET_OVERHEAD <= room {
+            self.send_sealed(socket, connection, MESSAGE_LABEL, plaintext, Data::Message, endpoint);
+            return;
+        }
+        if !connection.capabilities().contains(Capability::Fragmentation) {
+            println!("Message to {} does not fit into a datagram and the peer cannot reassemble fragments", connection.remote_x25519_id_hash);
+            return;
+        }
+
+        let message_id = match &mut connection.state {
+            connection::State::Established { session, .. } => {
+                session.next_message_id += 1;
+                session.next_message_id
+            },
+            _ => return,
+        };
+        let fragment_size = room - FRAGMENT_OVERHEAD;
+        let count = plaintext.chunks(fragment_size).len() as u16;
+        for (index, data) in plaintext.chunks(fragment_size).enumerate() {
+            let fragment = bincode::serialize(&FragmentPayload {
+                message_id,
+                index: index as u16,
+                count,
+                data: data.to_vec(),
+            }).unwrap();
+            self.send_sealed(socket, connection, FRAGMENT_LABEL, &fragment, Data::Fragment, endpoint);
+        }
+    }
+
+    pub(super) fn send_message(&self, socket: &UdpSocket, connection: &mut Connection, body: &str, delivery: Delivery) {
+        if body.len() > MAX_BODY_SIZE {
+            println!("Message to {} is too large", connection.remote_x25519_id_hash);
+            return;
+        }
+
+        if let (connection::State::Established { session, capabilities, .. }, Some(endpoint)) = (&mut connection.state, connection.endpoint) {
+            // The peer would neither acknowledge nor reorder the message
+            let delivery = if capabilities.contains(Capability::ReliableDelivery) { delivery } else { Delivery::Unreliable };
+            let (header, ciphertext) = match session.ratchet.encrypt(body.as_bytes(), connection.local_x25519_id_hash.as_ref()) {
+                Ok(encrypted) => encrypted,
+                Err(error) => {
+                    println!("Failed to encrypt message to {}: {}", connection.remote_x25519_id_hash, error);
+                    return;
+                },
+            };
+            let sequence = match delivery {
+                Delivery::Reliable => Some(session.send_buffer.allocate()),
+                Delivery::Unreliable => None,
+            };
+            let plaintext = bincode::serialize(&MessagePayload { sequence, header, ciphertext }).unwrap();
+            match sequence {
+                Some(sequence) => {
+                    session.send_buffer.queue(sequence, plaintext);
+                    self.send_queued_messages(socket, connection, Instant::now());
+                },
+                // Unreliable messages are not held back by congestion control
+                None => self.send_message_payload(socket, connection, &plaintext, endpoint),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::UdpSocket,
+        time::{ Duration, Instant },
+    };
+    use rand::{ thread_rng, RngCore };
+    use crate::{
+        x25519::{ PrivateKey, PublicKey },
+        SharedMacSecret,
+        instance::{
+            Instance, InstanceBuilder, Response, connection, Capabilities, PaddingPolicy, MessagePayload, DisconnectReason,
+            AckPayload, RttEstimator, SendBuffer, ReceiveBuffer,
+            Reassembly, ReassemblyError, reassembly::{ MAX_MESSAGE_SIZE, REASSEMBLY_TIMEOUT },
+            CongestionController, NewReno, Cubic, Pacer, new_reno::{ INITIAL_WINDOW, MIN_WINDOW },
+            path_mtu::BASE_PLPMTU,
+            test_util::{ connection, fragment, pair },
+        },
+    };
+
+    #[test]
+    fn rtt_estimation() {
+        let mut rtt = RttEstimator::default();
+        assert_eq!(rtt.rto, Duration::from_secs(1));
+
+        rtt.sample(Duration::from_millis(100));
+        assert_eq!(rtt.srtt, Some(Duration::from_millis(100)));
+        assert_eq!(rtt.rttvar, Duration::from_millis(50));
+        assert_eq!(rtt.rto, Duration::from_millis(300));
+
+        rtt.sample(Duration::from_millis(100));
+        assert_eq!(rtt.rttvar, Duration::from_micros(37500));
+        assert_eq!(rtt.rto, Duration::from_millis(250));
+
+        rtt.back_off();
+        assert_eq!(rtt.rto, Duration::from_millis(500));
+
+        // Never below the minimum however stable the path is
+        for _ in 0..64 {
+            rtt.sample(Duration::from_millis(1));
+        }
+        assert_eq!(rtt.rto, Duration::from_millis(200));
+    }
+
+    #[test]
+    fn messages_are_delivered_in_order_and_selectively_acknowledged() {
+        let mut buffer = ReceiveBuffer::default();
+
+        assert!(buffer.insert(1, b"1".to_vec()).is_empty());
+        assert!(buffer.insert(3, b"3".to_vec()).is_empty());
+        assert!(!buffer.accepts(3));
+        assert_eq!(buffer.ack(), AckPayload { cumulative: 0, ranges: vec![(1, 2), (3, 4)] });
+
+        assert_eq!(buffer.insert(0, b"0".to_vec()), vec![b"0".to_vec(), b"1".to_vec()]);
+        assert_eq!(buffer.ack(), AckPayload { cumulative: 2, ranges: vec![(3, 4)] });
+        assert!(!buffer.accepts(1));
+
+        assert_eq!(buffer.insert(2, b"2".to_vec()), vec![b"2".to_vec(), b"3".to_vec()]);
+        assert_eq!(buffer.ack(), AckPayload { cumulative: 4, ranges: vec![] });
+    }
+
+    #[test]
+    fn skipped_messages_are_declared_lost() {
+        let mut buffer = SendBuffer::default();
+        let now = Instant::now();
+
+        for sequence in 0..5 {
+            assert_eq!(buffer.allocate(), sequence);
+            buffer.sent(sequence, vec![sequence as u8], now + Duration::from_millis(sequence));
+        }
+
+        let acknowledgement = buffer.acknowledge(&AckPayload { cumulative: 0, ranges: vec![(1, 4)] }, now + Duration::from_millis(100));
+        assert_eq!(acknowledgement.rtt, Some(Duration::from_millis(97)));
+        assert_eq!(acknowledgement.bytes, 3);
+        assert_eq!(acknowledgement.sent_at, Some(now + Duration::from_millis(3)));
+        assert_eq!(buffer.in_flight.keys().copied().collect::<Vec<u64>>(), vec![0, 4]);
+        assert_eq!(buffer.lost(), vec![0]);
+
+        // Once sent again it has to be skipped over anew
+        buffer.retransmit(0, now + Duration::from_millis(100));
+        assert!(buffer.lost().is_empty());
+        assert_eq!(buffer.acknowledge(&AckPayload { cumulative: 5, ranges: vec![] }, now + Duration::from_millis(200)).rtt, Some(Duration::from_millis(196)));
+        assert!(buffer.in_flight.is_empty());
+    }
+
+    #[test]
+    fn fragments_are_reassembled_in_any_order() {
+        let mut reassembly = Reassembly::default();
+        let now = Instant::now();
+
+        assert_eq!(reassembly.insert(fragment(1, 2, 3, b"c"), now), Ok(None));
+        assert_eq!(reassembly.insert(fragment(2, 0, 2, b"x"), now), Ok(None));
+        assert_eq!(reassembly.insert(fragment(1, 0, 3, b"a"), now), Ok(None));
+        assert_eq!(reassembly.insert(fragment(1, 0, 3, b"a"), now), Ok(None));
+        assert_eq!(reassembly.insert(fragment(1, 1, 3, b"b"), now), Ok(Some(b"abc".to_vec())));
+
+        assert_eq!(reassembly.insert(fragment(2, 1, 3, b"y"), now), Err(ReassemblyError::InvalidFragment));
+        assert_eq!(reassembly.insert(fragment(3, 3, 3, b"z"), now), Err(ReassemblyError::InvalidFragment));
+        assert_eq!(reassembly.insert(fragment(3, 0, 0, b"z"), now), Err(ReassemblyError::InvalidFragment));
+    }
+
+    #[test]
+    fn reassembly_is_bounded() {
+        let mut reassembly = Reassembly::default();
+        let now = Instant::now();
+        let half = vec![0u8; MAX_MESSAGE_SIZE / 2];
+
+        assert_eq!(reassembly.insert(fragment(1, 0, 3, &half), now), Ok(None));
+        assert_eq!(reassembly.insert(fragment(1, 1, 3, &half), now), Ok(None));
+        assert_eq!(reassembly.insert(fragment(1, 2, 3, b"!"), now), Err(ReassemblyError::TooLarge));
+        assert_eq!(reassembly.next_timeout(), None);
+
+        // Partial messages pile up until the memory cap is hit
+        let mut message_id = 2;
+        let result = loop {
+            let result = reassembly.insert(fragment(message_id, 0, 2, &half), now);
+            if result.is_err() {
+                break result;
+            }
+            message_id += 1;
+        };
+        assert_eq!(result, Err(ReassemblyError::OutOfMemory));
+
+        assert_eq!(reassembly.next_timeout(), Some(now + REASSEMBLY_TIMEOUT));
+        reassembly.expire(now + REASSEMBLY_TIMEOUT);
+        assert_eq!(reassembly.next_timeout(), None);
+        assert_eq!(reassembly.insert(fragment(message_id, 0, 2, &half), now), Ok(None));
+    }
+
+    #[test]
+    fn new_reno_reacts_once_per_congestion_event() {
+        let mut new_reno = NewReno::default();
+        let rtt = RttEstimator::default();
+        let start = Instant::now();
+
+        // Slow start doubles the window every round trip
+        new_reno.on_ack(INITIAL_WINDOW, start, &rtt, start);
+        assert_eq!(new_reno.window(), 2 * INITIAL_WINDOW);
+
+        let lost_at = start + Duration::from_millis(1);
+        let now = start + Duration::from_millis(100);
+        new_reno.on_congestion_event(lost_at, now);
+        assert_eq!(new_reno.window(), INITIAL_WINDOW);
+        // Further losses and acknowledgements from the same flight are part of the same event
+        new_reno.on_congestion_event(lost_at, now + Duration::from_millis(1));
+        new_reno.on_ack(BASE_PLPMTU, lost_at, &rtt, now + Duration::from_millis(1));
+        assert_eq!(new_reno.window(), INITIAL_WINDOW);
+
+        // Congestion avoidance grows by one packet per window
+        new_reno.on_ack(INITIAL_WINDOW, now + Duration::from_millis(1), &rtt, now + Duration::from_millis(200));
+        assert_eq!(new_reno.window(), INITIAL_WINDOW + BASE_PLPMTU);
+
+        new_reno.on_timeout(now + Duration::from_millis(300));
+        assert_eq!(new_reno.window(), MIN_WINDOW);
+    }
+
+    #[test]
+    fn cubic_recovers_towards_the_previous_window() {
+        let mut cubic = Cubic::default();
+        let mut rtt = RttEstimator::default();
+        rtt.sample(Duration::from_millis(50));
+        let start = Instant::now();
+
+        cubic.on_ack(INITIAL_WINDOW, start, &rtt, start);
+        let before = cubic.window();
+        cubic.on_congestion_event(start, start + Duration::from_millis(50));
+        assert_eq!(cubic.window(), (before as f64 * 0.7) as usize);
+
+        // Growth is concave up to the old window and never beyond half of it per round trip
+        let mut now = start + Duration::from_millis(100);
+        let mut previous = cubic.window();
+        while cubic.window() < before {
+            cubic.on_ack(cubic.window(), now, &rtt, now);
+            assert!(cubic.window() > previous && cubic.window() <= previous * 3 / 2 + 1);
+            previous = cubic.window();
+            now += Duration::from_millis(50);
+        }
+        assert!(now - start < Duration::from_secs(10));
+
+        cubic.on_timeout(now);
+        assert_eq!(cubic.window(), MIN_WINDOW);
+    }
+
+    #[test]
+    fn pacing_spreads_the_window_over_the_round_trip() {
+        let mut pacer = Pacer::default();
+        let now = Instant::now();
+
+        pacer.on_sent(BASE_PLPMTU, INITIAL_WINDOW, None, now);
+        assert!(pacer.can_send(now));
+
+        pacer.on_sent(BASE_PLPMTU, INITIAL_WINDOW, Some(Duration::from_millis(125)), now);
+        assert!(!pacer.can_send(now));
+        // A tenth of the window at 1.25 times the window per round trip
+        assert_eq!(pacer.next_send_at, Some(now + Duration::from_millis(10)));
+        assert!(pacer.can_send(now + Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn undecryptable_reliable_messages_end_the_session() {
+        let mut rng = thread_rng();
+        let mut secret = [0u8; 128];
+        rng.fill_bytes(&mut secret);
+        let (instance, (_tx, rx)) = Instance::new(InstanceBuilder::new(), PrivateKey::new(&secret).unwrap(), PublicKey::new(&secret).unwrap());
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let (mut alice, bob) = pair();
+        let mut connection = connection(SharedMacSecret::new(&mut rng));
+        connection.state = connection::State::Established { session: Box::new(bob), capabilities: Capabilities::all(), padding_policy: PaddingPolicy::None };
+        let x25519_id_hash = connection.remote_x25519_id_hash;
+
+        let mut corrupt = |sequence| {
+            let (header, mut ciphertext) = alice.ratchet.encrypt(b"hello", x25519_id_hash.as_ref()).unwrap();
+            ciphertext[0] ^= 1;
+            bincode::serialize(&MessagePayload { sequence, header, ciphertext }).unwrap()
+        };
+
+        // A lost unreliable message is nothing to give up over
+        instance.handle_message(&socket, &mut rng, &mut connection, x25519_id_hash, &corrupt(None));
+        assert!(matches!(connection.state, connection::State::Established { .. }));
+        assert!(rx.try_recv().is_err());
+
+        instance.handle_message(&socket, &mut rng, &mut connection, x25519_id_hash, &corrupt(Some(0)));
+        assert!(matches!(connection.state, connection::State::Disconnected { reason: DisconnectReason::UndecryptableMessage, .. }));
+        assert!(matches!(rx.try_recv(), Ok(Response::Disconnected { reason: DisconnectReason::UndecryptableMessage, .. })));
+    }
+}
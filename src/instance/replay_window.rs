@@ -0,0 +1,66 @@
+use crate::instance::SessionError;
+
+const WORD_BITS: u64 = 64;
+const WINDOW_WORDS: usize = 32;
+// One word of the ring is always being recycled, so it does not count towards the window
+pub const WINDOW_SIZE: u64 = (WINDOW_WORDS as u64 - 1) * WORD_BITS;
+
+// Sliding window over received packet counters as described in RFC 6479
+#[derive(Debug, Clone)]
+pub struct ReplayWindow {
+    // One past the highest counter seen so far
+    next: u64,
+    bitmap: [u64; WINDOW_WORDS],
+}
+
+impl Default for ReplayWindow {
+    fn default() -> Self {
+        ReplayWindow {
+            next: 0,
+            bitmap: [0u64; WINDOW_WORDS],
+        }
+    }
+}
+
+impl ReplayWindow {
+    fn position(counter: u64) -> (usize, u64) {
+        (((counter / WORD_BITS) % WINDOW_WORDS as u64) as usize, 1 << (counter % WORD_BITS))
+    }
+
+    pub fn is_newest(&self, counter: u64) -> bool {
+        counter >= self.next
+    }
+
+    // Only checks, the window must not move until the packet has been authenticated
+    pub fn check(&self, counter: u64) -> Result<(), SessionError> {
+        if counter >= self.next {
+            return Ok(());
+        }
+        if self.next - counter > WINDOW_SIZE {
+            return Err(SessionError::TooOld);
+        }
+
+        let (word, bit) = ReplayWindow::position(counter);
+        if self.bitmap[word] & bit != 0 {
+            return Err(SessionError::Duplicate);
+        }
+
+        Ok(())
+    }
+
+    pub fn update(&mut self, counter: u64) {
+        if counter >= self.next {
+            let current_word = self.next.saturating_sub(1) / WORD_BITS;
+            let new_word = counter / WORD_BITS;
+            let cleared = (new_word - current_word).min(WINDOW_WORDS as u64);
+            for i in 1..=cleared {
+                self.bitmap[((current_word + i) % WINDOW_WORDS as u64) as usize] = 0;
+            }
+
+            self.next = counter.saturating_add(1);
+        }
+
+        let (word, bit) = ReplayWindow::position(counter);
+        self.bitmap[word] |= bit;
+    }
+}
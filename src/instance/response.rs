@@ -1,11 +1,26 @@
 use std::collections::HashMap;
 use crate::{
     x25519IDHash,
-    instance::Connection,
+    instance::{ ConnectionSummary, FailureReason, DisconnectReason },
 };
 
 pub enum Response {
     ListConnections {
-        connections: HashMap<x25519IDHash, Connection>
-    }
-}
\ No newline at end of file
+        connections: HashMap<x25519IDHash, ConnectionSummary>
+    },
+    Message {
+        x25519_id_hash: x25519IDHash,
+        body: String,
+    },
+    Connected {
+        x25519_id_hash: x25519IDHash,
+    },
+    Disconnected {
+        x25519_id_hash: x25519IDHash,
+        reason: DisconnectReason,
+    },
+    ConnectionFailed {
+        x25519_id_hash: x25519IDHash,
+        reason: FailureReason,
+    },
+}
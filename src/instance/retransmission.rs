@@ -0,0 +1,41 @@
+use std::time::Instant;
+use rand::prelude::ThreadRng;
+use crate::instance::RetransmitPolicy;
+
+#[derive(Debug, Clone)]
+pub enum Retransmit {
+    // Start over with a fresh initiation, resending the old one would look like a replay
+    Initiation,
+    // The last handshake message and our key confirmation, resent until the peer confirms the session
+    FinalMessages(Vec<Vec<u8>>),
+    // The peer has to send the next message, only the deadline applies
+    Wait,
+}
+
+#[derive(Debug, Clone)]
+pub struct Retransmission {
+    pub retransmit: Retransmit,
+    pub started_at: Instant,
+    pub next_at: Instant,
+    pub attempts: u32,
+}
+
+impl Retransmission {
+    pub fn new(retransmit: Retransmit, started_at: Instant, now: Instant, policy: &RetransmitPolicy, rng: &mut ThreadRng) -> Self {
+        Retransmission {
+            retransmit,
+            started_at,
+            next_at: now + policy.delay(0, rng),
+            attempts: 0,
+        }
+    }
+
+    pub fn deadline(&self, policy: &RetransmitPolicy) -> Instant {
+        self.started_at + policy.timeout
+    }
+
+    pub fn back_off(&mut self, now: Instant, policy: &RetransmitPolicy, rng: &mut ThreadRng) {
+        self.attempts += 1;
+        self.next_at = now + policy.delay(self.attempts, rng);
+    }
+}
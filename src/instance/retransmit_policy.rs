@@ -0,0 +1,37 @@
+use std::time::Duration;
+use rand::{
+    prelude::ThreadRng,
+    Rng,
+};
+
+#[derive(Debug, Copy, Clone)]
+pub struct RetransmitPolicy {
+    pub initial_interval: Duration,
+    pub max_interval: Duration,
+    // A handshake that has not completed by then is given up on
+    pub timeout: Duration,
+}
+
+impl Default for RetransmitPolicy {
+    fn default() -> Self {
+        RetransmitPolicy {
+            initial_interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(16),
+            timeout: Duration::from_secs(90),
+        }
+    }
+}
+
+impl RetransmitPolicy {
+    // Doubles with every attempt, the jitter keeps peers that lost packets at the same time from
+    // retrying in lockstep
+    pub fn delay(&self, attempts: u32, rng: &mut ThreadRng) -> Duration {
+        let delay = self.initial_interval
+            .checked_mul(1 << attempts.min(16))
+            .unwrap_or(self.max_interval)
+            .min(self.max_interval);
+        let jitter = delay.as_millis() as u64 / 4;
+
+        delay + Duration::from_millis(rng.gen_range(0, jitter + 1))
+    }
+}
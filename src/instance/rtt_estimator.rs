@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+const INITIAL_RTO: Duration = Duration::from_secs(1);
+const MIN_RTO: Duration = Duration::from_millis(200);
+const MAX_RTO: Duration = Duration::from_secs(60);
+const CLOCK_GRANULARITY: Duration = Duration::from_millis(1);
+
+// Retransmission timeout as described in RFC 6298
+#[derive(Debug, Copy, Clone)]
+pub struct RttEstimator {
+    pub srtt: Option<Duration>,
+    pub rttvar: Duration,
+    pub rto: Duration,
+    // Timeouts since the last sample
+    pub timeouts: u32,
+}
+
+impl Default for RttEstimator {
+    fn default() -> Self {
+        RttEstimator {
+            srtt: None,
+            rttvar: Duration::from_secs(0),
+            rto: INITIAL_RTO,
+            timeouts: 0,
+        }
+    }
+}
+
+impl RttEstimator {
+    pub fn sample(&mut self, rtt: Duration) {
+        let srtt = match self.srtt {
+            Some(srtt) => {
+                self.rttvar = self.rttvar * 3 / 4 + srtt.abs_diff(rtt) / 4;
+                srtt * 7 / 8 + rtt / 8
+            },
+            None => {
+                self.rttvar = rtt / 2;
+                rtt
+            },
+        };
+        self.srtt = Some(srtt);
+        self.timeouts = 0;
+
+        self.rto = (srtt + (self.rttvar * 4).max(CLOCK_GRANULARITY)).max(MIN_RTO).min(MAX_RTO);
+    }
+
+    // Called when the timeout fired, the next sample undoes it
+    pub fn back_off(&mut self) {
+        self.rto = (self.rto * 2).min(MAX_RTO);
+        self.timeouts += 1;
+    }
+}
@@ -0,0 +1,8 @@
+// Post-handshake payload encrypted with the traffic keys of the given generation, the counter
+// doubles as the nonce and only ever increases within a session
+#[derive(Debug, Clone)]
+pub struct Sealed {
+    pub generation: u32,
+    pub counter: u64,
+    pub ciphertext: Vec<u8>,
+}
@@ -0,0 +1,115 @@
+use std::{
+    time::{ Duration, Instant },
+    collections::{ BTreeMap, VecDeque },
+};
+use crate::instance::AckPayload;
+
+// A message counts as lost once this many sent after it were acknowledged
+const PACKET_THRESHOLD: u64 = 3;
+
+#[derive(Debug, Clone)]
+pub struct InFlight {
+    // The `MessagePayload` as it was sealed, it is sealed again for every retransmission
+    pub plaintext: Vec<u8>,
+    pub sent_at: Instant,
+    // Acknowledgements for retransmitted messages are ambiguous and not used as RTT samples
+    pub retransmitted: bool,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Acknowledgement {
+    pub rtt: Option<Duration>,
+    pub bytes: usize,
+    // When the newest of the acknowledged messages was sent
+    pub sent_at: Option<Instant>,
+}
+
+// Reliable messages that wait to be sent or to be acknowledged
+#[derive(Debug, Clone, Default)]
+pub struct SendBuffer {
+    pub next_sequence: u64,
+    // Waiting for room in the congestion window
+    pub queued: VecDeque<(u64, Vec<u8>)>,
+    pub in_flight: BTreeMap<u64, InFlight>,
+    // Largest acknowledged sequence number and when it was sent
+    pub largest_acknowledged: Option<(u64, Instant)>,
+}
+
+impl SendBuffer {
+    pub fn allocate(&mut self) -> u64 {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        sequence
+    }
+
+    pub fn queue(&mut self, sequence: u64, plaintext: Vec<u8>) {
+        self.queued.push_back((sequence, plaintext));
+    }
+
+    pub fn bytes_in_flight(&self) -> usize {
+        self.in_flight.values().map(|in_flight| in_flight.plaintext.len()).sum()
+    }
+
+    pub fn sent(&mut self, sequence: u64, plaintext: Vec<u8>, now: Instant) {
+        self.in_flight.insert(sequence, InFlight {
+            plaintext,
+            sent_at: now,
+            retransmitted: false,
+        });
+    }
+
+    // Forgets the acknowledged messages, the RTT sample is only present if one of them allows it
+    pub fn acknowledge(&mut self, ack: &AckPayload, now: Instant) -> Acknowledgement {
+        let acknowledged: Vec<u64> = self.in_flight.keys()
+            .copied()
+            .filter(|sequence| ack.acknowledges(*sequence))
+            .collect();
+
+        let mut acknowledgement = Acknowledgement::default();
+        for sequence in acknowledged {
+            if let Some(in_flight) = self.in_flight.remove(&sequence) {
+                if !in_flight.retransmitted {
+                    acknowledgement.rtt = Some(now.duration_since(in_flight.sent_at));
+                }
+                acknowledgement.bytes += in_flight.plaintext.len();
+                acknowledgement.sent_at = acknowledgement.sent_at.max(Some(in_flight.sent_at));
+                if self.largest_acknowledged.is_none_or(|(largest, _)| sequence > largest) {
+                    self.largest_acknowledged = Some((sequence, in_flight.sent_at));
+                }
+            }
+        }
+
+        acknowledgement
+    }
+
+    // Messages the acknowledgements skipped over, a retransmission is only considered lost again
+    // once something sent after it was acknowledged
+    pub fn lost(&self) -> Vec<u64> {
+        let (largest, largest_sent_at) = match self.largest_acknowledged {
+            Some(largest_acknowledged) => largest_acknowledged,
+            None => return Vec::new(),
+        };
+
+        self.in_flight.iter()
+            .filter(|(sequence, in_flight)| *sequence + PACKET_THRESHOLD <= largest && in_flight.sent_at < largest_sent_at)
+            .map(|(sequence, _)| *sequence)
+            .collect()
+    }
+
+    pub fn timed_out(&self, rto: Duration, now: Instant) -> bool {
+        self.next_timeout(rto).is_some_and(|timeout| timeout <= now)
+    }
+
+    pub fn retransmit(&mut self, sequence: u64, now: Instant) -> Option<Vec<u8>> {
+        let in_flight = self.in_flight.get_mut(&sequence)?;
+        in_flight.sent_at = now;
+        in_flight.retransmitted = true;
+
+        Some(in_flight.plaintext.clone())
+    }
+
+    pub fn next_timeout(&self, rto: Duration) -> Option<Instant> {
+        self.in_flight.values().map(|in_flight| in_flight.sent_at + rto).min()
+    }
+}
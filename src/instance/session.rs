@@ -0,0 +1,161 @@
+use std::time::Instant;
+use openssl::error::ErrorStack;
+use crate::{
+    x25519::PublicKey,
+    ratchet::DoubleRatchet,
+    instance::{ SessionKeys, Role, Sealed, SessionError, ReplayWindow, SendBuffer, ReceiveBuffer, RttEstimator, Reassembly, CongestionController, Pacer },
+};
+
+#[derive(Debug)]
+pub struct Session {
+    pub remote_public_key: PublicKey,
+    pub role: Role,
+    pub generation: u32,
+    pub session_keys: SessionKeys,
+    // Kept around until the next rekey so packets sent before the switch still open
+    pub previous_session_keys: Option<SessionKeys>,
+    // Present while our own rekey request is waiting for an acknowledgement
+    pub next_session_keys: Option<SessionKeys>,
+    pub ratchet: DoubleRatchet,
+    // Set once the peer proved it has the keys as well
+    pub confirmed: bool,
+    pub established_at: Instant,
+    pub rekeyed_at: Instant,
+    pub rekey_requested_at: Option<Instant>,
+    pub last_sent_at: Instant,
+    pub last_received_at: Instant,
+    // Counters run across generations so a single window covers packets of all of them
+    pub sending_counter: u64,
+    pub replay_window: ReplayWindow,
+    // Usage of the current generation
+    pub sent_messages: u64,
+    pub sent_bytes: u64,
+    pub send_buffer: SendBuffer,
+    pub receive_buffer: ReceiveBuffer,
+    pub rtt: RttEstimator,
+    pub congestion: Box<dyn CongestionController>,
+    pub pacer: Pacer,
+    pub next_message_id: u64,
+    pub reassembly: Reassembly,
+}
+
+impl Session {
+    pub fn new(remote_public_key: PublicKey, role: Role, session_keys: SessionKeys, ratchet: DoubleRatchet, congestion: Box<dyn CongestionController>) -> Self {
+        let now = Instant::now();
+
+        Session {
+            remote_public_key,
+            role,
+            generation: 0,
+            session_keys,
+            previous_session_keys: None,
+            next_session_keys: None,
+            ratchet,
+            confirmed: false,
+            established_at: now,
+            rekeyed_at: now,
+            rekey_requested_at: None,
+            last_sent_at: now,
+            last_received_at: now,
+            sending_counter: 0,
+            replay_window: ReplayWindow::default(),
+            sent_messages: 0,
+            sent_bytes: 0,
+            send_buffer: SendBuffer::default(),
+            receive_buffer: ReceiveBuffer::default(),
+            rtt: RttEstimator::default(),
+            congestion,
+            pacer: Pacer::default(),
+            next_message_id: 0,
+            reassembly: Reassembly::default(),
+        }
+    }
+
+    fn additional_data(generation: u32, label: &[u8], aad: &[u8]) -> Vec<u8> {
+        let mut additional_data = Vec::with_capacity(4 + label.len() + aad.len());
+        additional_data.extend_from_slice(&generation.to_le_bytes());
+        additional_data.extend_from_slice(label);
+        additional_data.extend_from_slice(aad);
+
+        additional_data
+    }
+
+    pub fn seal(&mut self, label: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<Sealed, SessionError> {
+        let counter = self.sending_counter;
+        if counter == u64::MAX {
+            return Err(SessionError::CounterExhausted);
+        }
+
+        let additional_data = Session::additional_data(self.generation, label, aad);
+        let ciphertext = self.session_keys.sending_key(self.role).encrypt(counter, &additional_data, plaintext)?;
+
+        self.sending_counter += 1;
+        self.last_sent_at = Instant::now();
+        self.sent_messages += 1;
+        self.sent_bytes += plaintext.len() as u64;
+
+        Ok(Sealed {
+            generation: self.generation,
+            counter,
+            ciphertext,
+        })
+    }
+
+    pub fn open(&mut self, label: &[u8], aad: &[u8], sealed: &Sealed) -> Result<Vec<u8>, SessionError> {
+        self.replay_window.check(sealed.counter)?;
+
+        let additional_data = Session::additional_data(sealed.generation, label, aad);
+        let keys = if sealed.generation == self.generation {
+            Some(&self.session_keys)
+        } else if Some(sealed.generation) == self.generation.checked_sub(1) {
+            self.previous_session_keys.as_ref()
+        } else if Some(sealed.generation) == self.generation.checked_add(1) {
+            self.next_session_keys.as_ref()
+        } else {
+            None
+        };
+        let plaintext = keys.ok_or(SessionError::UnknownGeneration)?
+            .receiving_key(self.role)
+            .decrypt(sealed.counter, &additional_data, &sealed.ciphertext)
+            .map_err(|_| SessionError::Decryption)?;
+
+        self.replay_window.update(sealed.counter);
+        self.confirmed = true;
+        self.last_received_at = Instant::now();
+
+        // The peer only sends with the next generation once it has seen our request, so this
+        // counts as an acknowledgement
+        if Some(sealed.generation) == self.generation.checked_add(1) {
+            self.advance()?;
+        }
+
+        Ok(plaintext)
+    }
+
+    // Returns the generation to announce to the peer
+    pub fn request_rekey(&mut self, now: Instant) -> Result<u32, ErrorStack> {
+        if self.next_session_keys.is_none() {
+            self.next_session_keys = Some(self.session_keys.next()?);
+        }
+        self.rekey_requested_at = Some(now);
+
+        Ok(self.generation + 1)
+    }
+
+    // Switches to the next generation
+    pub fn advance(&mut self) -> Result<(), ErrorStack> {
+        let next_session_keys = match self.next_session_keys.take() {
+            Some(keys) => keys,
+            None => self.session_keys.next()?,
+        };
+
+        self.previous_session_keys = Some(std::mem::replace(&mut self.session_keys, next_session_keys));
+        self.generation += 1;
+        self.rekeyed_at = Instant::now();
+        self.rekey_requested_at = None;
+        self.sent_messages = 0;
+        self.sent_bytes = 0;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+use std::fmt::{ Formatter, Display, Error };
+use openssl::error::ErrorStack;
+
+#[derive(Debug, Clone)]
+pub enum SessionError {
+    Crypto(ErrorStack),
+    Decryption,
+    UnknownGeneration,
+    Duplicate,
+    TooOld,
+    CounterExhausted,
+}
+
+impl Display for SessionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            SessionError::Crypto(error) => write!(f, "{}", error),
+            SessionError::Decryption => f.write_str("failed to decrypt packet"),
+            SessionError::UnknownGeneration => f.write_str("packet uses an unknown key generation"),
+            SessionError::Duplicate => f.write_str("packet was already received"),
+            SessionError::TooOld => f.write_str("packet is too old"),
+            SessionError::CounterExhausted => f.write_str("packet counter exhausted"),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<ErrorStack> for SessionError {
+    fn from(error: ErrorStack) -> Self {
+        SessionError::Crypto(error)
+    }
+}
@@ -0,0 +1,63 @@
+use std::fmt::{ Formatter, Debug, Error };
+use serde::{ Serialize, Deserialize };
+use openssl::{
+    symm::{ Cipher, encrypt_aead, decrypt_aead },
+    error::ErrorStack,
+};
+
+pub const NONCE_SIZE: usize = 12;
+pub const TAG_SIZE: usize = 16;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq)]
+pub struct SessionKey ([u8; 32]);
+
+impl SessionKey {
+    // Same nonce layout as Noise, 32 bits of zeros followed by the little-endian counter
+    fn nonce(counter: u64) -> [u8; NONCE_SIZE] {
+        let mut nonce = [0u8; NONCE_SIZE];
+        nonce[4..].copy_from_slice(&counter.to_le_bytes());
+
+        nonce
+    }
+
+    pub fn encrypt(&self, counter: u64, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, ErrorStack> {
+        let mut tag = [0u8; TAG_SIZE];
+        let mut ciphertext = encrypt_aead(Cipher::chacha20_poly1305(), &self.0, Some(&SessionKey::nonce(counter)), aad, plaintext, &mut tag)?;
+        ciphertext.extend_from_slice(&tag);
+
+        Ok(ciphertext)
+    }
+
+    pub fn decrypt(&self, counter: u64, aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, ErrorStack> {
+        if ciphertext.len() < TAG_SIZE {
+            return Err(ErrorStack::get());
+        }
+        let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - TAG_SIZE);
+
+        decrypt_aead(Cipher::chacha20_poly1305(), &self.0, Some(&SessionKey::nonce(counter)), aad, ciphertext, tag)
+    }
+}
+
+impl AsRef<[u8]> for SessionKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsMut<[u8]> for SessionKey {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+impl Debug for SessionKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.write_str("\"<session key>\"")
+    }
+}
+
+impl From<[u8; 32]> for SessionKey {
+    fn from(slice: [u8; 32]) -> Self {
+        SessionKey(slice)
+    }
+}
@@ -0,0 +1,50 @@
+use rand::{ thread_rng, RngCore };
+use crate::{
+    x25519::{ PublicKey, EphemeralSecret },
+    ratchet::DoubleRatchet,
+    x25519IDHash,
+    SharedMacSecret,
+    instance::{ Connection, Session, SessionKeys, Role, FragmentPayload, CongestionControl },
+};
+
+// A connection to a peer whose public key is not known yet, as added from the command line
+pub fn connection(shared_mac_secret: SharedMacSecret) -> Connection {
+    let x25519_id_hash = x25519IDHash::first_contact(shared_mac_secret);
+
+    Connection::new(x25519_id_hash, x25519_id_hash, shared_mac_secret, None, None)
+}
+
+pub fn fragment(message_id: u64, index: u16, count: u16, data: &[u8]) -> FragmentPayload {
+    FragmentPayload {
+        message_id,
+        index,
+        count,
+        data: data.to_vec(),
+    }
+}
+
+pub fn pair() -> (Session, Session) {
+    let mut rng = thread_rng();
+
+    let mut secret = [0u8; 128];
+    rng.fill_bytes(&mut secret);
+    let public_key = PublicKey::new(&secret).unwrap();
+
+    let mut initiator_to_responder = [0u8; 32];
+    rng.fill_bytes(&mut initiator_to_responder);
+    let mut responder_to_initiator = [0u8; 32];
+    rng.fill_bytes(&mut responder_to_initiator);
+    // Both sides derive their own copy, as they would from the handshake
+    let (alice_keys, root_key) = SessionKeys::derive(initiator_to_responder, responder_to_initiator, b"handshake hash").unwrap();
+    let (bob_keys, _) = SessionKeys::derive(initiator_to_responder, responder_to_initiator, b"handshake hash").unwrap();
+
+    let alice_secret = EphemeralSecret::new(&mut rng);
+    let bob_secret = EphemeralSecret::new(&mut rng);
+    let alice_public_key = alice_secret.public_key().unwrap();
+    let bob_public_key = bob_secret.public_key().unwrap();
+
+    (
+        Session::new(public_key, Role::Initiator, alice_keys, DoubleRatchet::new_initiator(root_key, alice_secret, bob_public_key).unwrap(), CongestionControl::default().controller()),
+        Session::new(public_key, Role::Responder, bob_keys, DoubleRatchet::new_responder(&mut rng, root_key, bob_secret, alice_public_key).unwrap(), CongestionControl::default().controller()),
+    )
+}
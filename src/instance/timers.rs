@@ -0,0 +1,44 @@
+use std::{
+    time::Instant,
+    cmp::Reverse,
+    collections::{ BinaryHeap, HashMap },
+};
+use crate::x25519IDHash;
+
+// Wakes connections up when something about them is due. Only the earliest wakeup per
+// connection is kept, later ones are left in the heap and skipped once they come up.
+#[derive(Debug, Default)]
+pub struct Timers {
+    queue: BinaryHeap<Reverse<(Instant, x25519IDHash)>>,
+    scheduled: HashMap<x25519IDHash, Instant>,
+}
+
+impl Timers {
+    pub fn schedule(&mut self, at: Instant, x25519_id_hash: x25519IDHash) {
+        match self.scheduled.get(&x25519_id_hash) {
+            Some(scheduled) if *scheduled <= at => {},
+            _ => {
+                self.scheduled.insert(x25519_id_hash, at);
+                self.queue.push(Reverse((at, x25519_id_hash)));
+            },
+        }
+    }
+
+    pub fn expired(&mut self, now: Instant) -> Vec<x25519IDHash> {
+        let mut expired = Vec::new();
+
+        while let Some(Reverse((at, x25519_id_hash))) = self.queue.peek().copied() {
+            if at > now {
+                break;
+            }
+            self.queue.pop();
+
+            if self.scheduled.get(&x25519_id_hash) == Some(&at) {
+                self.scheduled.remove(&x25519_id_hash);
+                expired.push(x25519_id_hash);
+            }
+        }
+
+        expired
+    }
+}
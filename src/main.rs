@@ -6,6 +6,10 @@ extern crate serde_big_array;
 
 mod instance;
 mod x25519;
+mod noise;
+mod ratchet;
+mod hkdf;
+mod options;
 mod x25519_id_hash;
 pub use x25519_id_hash::x25519IDHash;
 mod shared_mac_secret;
@@ -18,24 +22,78 @@ use std::{
 };
 use rand::{ thread_rng, RngCore };
 use crate::{
-    instance::{ InstanceBuilder, Instance },
-    x25519::{ PrivateKey, PublicKey },
+    instance::Instance,
+    x25519::{ PrivateKey, PublicKey, VerifyingKey, Signature },
 };
-use crate::instance::{Command, Response};
+use crate::instance::{Command, Response, Delivery};
+
+// Keys and signatures are printed in quotes, so they are accepted with or without them
+fn decode(input: Option<&&str>) -> Result<Vec<u8>, String> {
+    let input = input.ok_or_else(|| "missing argument".to_string())?;
+
+    base64::decode(input.trim_matches('"')).map_err(|error| error.to_string())
+}
+
+fn decode_exact(input: Option<&&str>, size: usize) -> Result<Vec<u8>, String> {
+    let bytes = decode(input)?;
+    if bytes.len() != size {
+        return Err(format!("expected {} bytes, got {}", size, bytes.len()));
+    }
+
+    Ok(bytes)
+}
 
 fn main() {
     let mut rng = thread_rng();
     let mut secret = [0u8; 128];
     rng.fill_bytes(&mut secret);
 
-    let private_key = PrivateKey::new(&secret);
-    let public_key = PublicKey::new(&secret);
+    let private_key = PrivateKey::new(&secret).unwrap();
+    let public_key = PublicKey::new(&secret).unwrap();
 
-    let instance_builder = InstanceBuilder::new();
+    let instance_builder = match options::instance_builder(std::env::args().skip(1)) {
+        Ok(instance_builder) => instance_builder,
+        Err(error) => {
+            println!("{}\n\n{}", error, options::USAGE);
+            std::process::exit(1);
+        },
+    };
     let (instance, (tx, rx)) = Instance::new(instance_builder, private_key, public_key);
 
     let joiner = instance.run();
 
+    let printer = std::thread::spawn(move || {
+        for response in rx {
+            match response {
+                Response::ListConnections { connections } => {
+                    for (x25519_id_hash, connection) in connections {
+                        println!("{}: {}", x25519_id_hash, connection.state);
+                        if let Some(remote_public_key) = connection.remote_public_key {
+                            println!("  public key: {}", remote_public_key);
+                        }
+                        if let Some(endpoint) = connection.endpoint {
+                            println!("  endpoint: {}", endpoint);
+                        }
+                        println!("  capabilities: {:?}, padding: {:?}, path MTU: {}", connection.capabilities, connection.padding_policy, connection.path_mtu);
+                        println!("  {:?}", connection.stats);
+                    }
+                },
+                Response::Message { x25519_id_hash, body } => {
+                    println!("{}: {}", x25519_id_hash, body);
+                },
+                Response::Connected { x25519_id_hash } => {
+                    println!("Connected to {}", x25519_id_hash);
+                },
+                Response::Disconnected { x25519_id_hash, reason } => {
+                    println!("Disconnected from {}: {}", x25519_id_hash, reason);
+                },
+                Response::ConnectionFailed { x25519_id_hash, reason } => {
+                    println!("Connection with {} failed: {}", x25519_id_hash, reason);
+                },
+            }
+        }
+    });
+
     let stdin = stdin();
     let mut input = String::new();
     loop {
@@ -50,10 +108,18 @@ fn main() {
                 println!("\"{}\"", base64::encode(&secret as &[u8]));
             },
             "privkey" => {
-                println!("{}", PrivateKey::new(base64::decode(input[1]).unwrap().as_slice()));
+                match decode(input.get(1)).map(|secret| PrivateKey::new(&secret)) {
+                    Ok(Ok(private_key)) => println!("{}", private_key),
+                    Ok(Err(error)) => println!("{}", error),
+                    Err(error) => println!("Invalid secret: {}", error),
+                }
             },
             "pubkey" => {
-                println!("{}", PublicKey::new(base64::decode(input[1]).unwrap().as_slice()));
+                match decode(input.get(1)).map(|secret| PublicKey::new(&secret)) {
+                    Ok(Ok(public_key)) => println!("{}", public_key),
+                    Ok(Err(error)) => println!("{}", error),
+                    Err(error) => println!("Invalid secret: {}", error),
+                }
             },
             "shared_mac_secret" => {
                 println!("{}", SharedMacSecret::new(&mut rng));
@@ -64,37 +130,80 @@ fn main() {
                 break
             },
             "add_connection" => {
-                let shared_mac_secret = SharedMacSecret::from(base64::decode(input[1]).unwrap());
-                let public_key = PublicKey::from(base64::decode(input[2]).unwrap());
+                // The public key is optional, without it the peer's key is learned in the handshake
+                let public_key = input.get(2).map(|public_key| decode_exact(Some(public_key), 32)).transpose();
 
-                tx.send(Command::AddConnection {
-                    public_key,
-                    shared_mac_secret,
-                }).unwrap();
+                match (decode_exact(input.get(1), 32), public_key) {
+                    (Ok(shared_mac_secret), Ok(public_key)) => {
+                        tx.send(Command::AddConnection {
+                            public_key: public_key.map(PublicKey::from),
+                            shared_mac_secret: SharedMacSecret::from(shared_mac_secret),
+                        }).unwrap();
+                    },
+                    (Err(error), _) => println!("Invalid shared MAC secret: {}", error),
+                    (_, Err(error)) => println!("Invalid public key: {}", error),
+                }
             },
             "connect" => {
-                let x25519_id_hash = x25519IDHash::from(base64::decode(input[1]).unwrap());
-                let endpoint = input[2].parse().unwrap();
+                let endpoint = input.get(2)
+                    .ok_or_else(|| "missing argument".to_string())
+                    .and_then(|endpoint| endpoint.parse::<SocketAddr>().map_err(|error| error.to_string()));
 
-                tx.send(Command::Connect {
-                    x25519_id_hash,
-                    endpoint
-                }).unwrap();
+                match (decode_exact(input.get(1), 32), endpoint) {
+                    (Ok(x25519_id_hash), Ok(endpoint)) => {
+                        tx.send(Command::Connect {
+                            x25519_id_hash: x25519IDHash::from(x25519_id_hash),
+                            endpoint
+                        }).unwrap();
+                    },
+                    (Err(error), _) => println!("Invalid connection: {}", error),
+                    (_, Err(error)) => println!("Invalid endpoint: {}", error),
+                }
             },
             "list_connections" => {
                 tx.send(Command::ListConnections).unwrap();
+            },
+            "send" | "send_unreliable" => {
+                let delivery = if input[0] == "send" { Delivery::Reliable } else { Delivery::Unreliable };
 
-                if let Response::ListConnections { connections } = rx.recv().unwrap() { //@TODO no unwrap maybe?
-                    println!("{:?}", connections);
+                match decode_exact(input.get(1), 32) {
+                    Ok(x25519_id_hash) => {
+                        tx.send(Command::SendMessage {
+                            x25519_id_hash: x25519IDHash::from(x25519_id_hash),
+                            body: input[2..].join(" "),
+                            delivery
+                        }).unwrap();
+                    },
+                    Err(error) => println!("Invalid connection: {}", error),
+                }
+            },
+            "sign" => {
+                match private_key.sign(input[1..].join(" ").as_bytes()) {
+                    Ok(signature) => println!("{}", signature),
+                    Err(error) => println!("{}", error),
+                }
+            },
+            "verify" => {
+                match (decode_exact(input.get(1), 32), decode_exact(input.get(2), 64)) {
+                    (Ok(verifying_key), Ok(signature)) => {
+                        match VerifyingKey::from(verifying_key).verify(input[3..].join(" ").as_bytes(), &Signature::from(signature)) {
+                            Ok(()) => println!("Valid signature"),
+                            Err(error) => println!("{}", error),
+                        }
+                    },
+                    (Err(error), _) => println!("Invalid verifying key: {}", error),
+                    (_, Err(error)) => println!("Invalid signature: {}", error),
                 }
             },
             "info" => {
                 println!("public key: {}", public_key);
                 println!("private key: {}", private_key);
+                println!("verifying key: {}", VerifyingKey::new(&private_key));
             },
             _ => { println!("Unknown command"); }
         }
     }
 
     joiner.join().unwrap();
+    printer.join().unwrap();
 }
\ No newline at end of file
@@ -0,0 +1,78 @@
+use openssl::symm::{ Cipher, encrypt_aead, decrypt_aead };
+use crate::noise::NoiseError;
+
+pub const KEY_SIZE: usize = 32;
+pub const TAG_SIZE: usize = 16;
+
+#[derive(Clone, Default)]
+pub struct CipherState {
+    k: Option<[u8; KEY_SIZE]>,
+    n: u64,
+}
+
+impl CipherState {
+    pub fn new(k: Option<[u8; KEY_SIZE]>) -> Self {
+        CipherState {
+            k,
+            n: 0,
+        }
+    }
+
+    pub fn has_key(&self) -> bool {
+        self.k.is_some()
+    }
+
+    pub fn key(&self) -> Option<[u8; KEY_SIZE]> {
+        self.k
+    }
+
+    fn nonce_bytes(n: u64) -> [u8; 12] {
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&n.to_le_bytes());
+
+        nonce
+    }
+
+    pub fn encrypt_with_ad(&mut self, ad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, NoiseError> {
+        let k = match self.k {
+            Some(k) => k,
+            None => return Ok(plaintext.to_vec()),
+        };
+
+        if self.n == u64::MAX {
+            return Err(NoiseError::NonceExhausted);
+        }
+
+        let mut tag = [0u8; TAG_SIZE];
+        let mut ciphertext = encrypt_aead(Cipher::chacha20_poly1305(), &k, Some(&Self::nonce_bytes(self.n)), ad, plaintext, &mut tag)
+            .map_err(|_| NoiseError::Encryption)?;
+        ciphertext.extend_from_slice(&tag);
+
+        self.n += 1;
+
+        Ok(ciphertext)
+    }
+
+    pub fn decrypt_with_ad(&mut self, ad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, NoiseError> {
+        let k = match self.k {
+            Some(k) => k,
+            None => return Ok(ciphertext.to_vec()),
+        };
+
+        if self.n == u64::MAX {
+            return Err(NoiseError::NonceExhausted);
+        }
+
+        if ciphertext.len() < TAG_SIZE {
+            return Err(NoiseError::MessageTooShort);
+        }
+        let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - TAG_SIZE);
+
+        let plaintext = decrypt_aead(Cipher::chacha20_poly1305(), &k, Some(&Self::nonce_bytes(self.n)), ad, ciphertext, tag)
+            .map_err(|_| NoiseError::Decryption)?;
+
+        self.n += 1;
+
+        Ok(plaintext)
+    }
+}
@@ -0,0 +1,86 @@
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Token {
+    E,
+    S,
+    EE,
+    ES,
+    SE,
+    SS,
+    Psk,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandshakePattern {
+    name: String,
+    initiator_pre_message: Vec<Token>,
+    responder_pre_message: Vec<Token>,
+    messages: Vec<Vec<Token>>,
+}
+
+impl HandshakePattern {
+    pub fn ik() -> Self {
+        HandshakePattern {
+            name: String::from("IK"),
+            initiator_pre_message: vec![],
+            responder_pre_message: vec![Token::S],
+            messages: vec![
+                vec![Token::E, Token::ES, Token::S, Token::SS],
+                vec![Token::E, Token::EE, Token::SE],
+            ],
+        }
+    }
+
+    pub fn xx() -> Self {
+        HandshakePattern {
+            name: String::from("XX"),
+            initiator_pre_message: vec![],
+            responder_pre_message: vec![],
+            messages: vec![
+                vec![Token::E],
+                vec![Token::E, Token::EE, Token::S, Token::ES],
+                vec![Token::S, Token::SE],
+            ],
+        }
+    }
+
+    // `psk0` prepends the token to the first message, `pskN` appends it to the Nth message
+    pub fn with_psk(mut self, position: usize) -> Self {
+        if position == 0 {
+            self.messages[0].insert(0, Token::Psk);
+        } else {
+            self.messages[position - 1].push(Token::Psk);
+        }
+
+        if self.name.contains("psk") {
+            self.name.push_str(format!("+psk{}", position).as_str());
+        } else {
+            self.name.push_str(format!("psk{}", position).as_str());
+        }
+
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn protocol_name(&self) -> String {
+        format!("Noise_{}_25519_ChaChaPoly_SHA256", self.name)
+    }
+
+    pub fn initiator_pre_message(&self) -> &[Token] {
+        &self.initiator_pre_message
+    }
+
+    pub fn responder_pre_message(&self) -> &[Token] {
+        &self.responder_pre_message
+    }
+
+    pub fn messages(&self) -> &[Vec<Token>] {
+        &self.messages
+    }
+
+    pub fn is_psk(&self) -> bool {
+        self.messages.iter().any(|message| message.contains(&Token::Psk))
+    }
+}
@@ -0,0 +1,253 @@
+use std::fmt::{ Formatter, Debug, Error };
+use crate::{
+    hkdf::HASH_SIZE,
+    x25519::{ PrivateKey, PublicKey, SharedKey, EphemeralSecret },
+    noise::{
+        CipherState,
+        SymmetricState,
+        HandshakePattern,
+        Token,
+        NoiseError,
+        cipher_state::TAG_SIZE,
+    },
+};
+
+const DH_SIZE: usize = 32;
+
+pub struct HandshakeState {
+    symmetric_state: SymmetricState,
+    pattern: HandshakePattern,
+    initiator: bool,
+    s: Option<(PrivateKey, PublicKey)>,
+    e: EphemeralSecret,
+    e_public_key: PublicKey,
+    rs: Option<PublicKey>,
+    re: Option<PublicKey>,
+    psk: Option<[u8; 32]>,
+    message_index: usize,
+}
+
+impl HandshakeState {
+    pub fn new(
+        pattern: HandshakePattern,
+        initiator: bool,
+        prologue: &[u8],
+        s: Option<(PrivateKey, PublicKey)>,
+        e: EphemeralSecret,
+        rs: Option<PublicKey>,
+        psk: Option<[u8; 32]>,
+    ) -> Result<Self, NoiseError> {
+        if pattern.is_psk() && psk.is_none() {
+            return Err(NoiseError::MissingKey);
+        }
+
+        let mut symmetric_state = SymmetricState::new(pattern.protocol_name().as_str());
+        symmetric_state.mix_hash(prologue);
+
+        let local_static_public_key = s.map(|(_, public_key)| public_key);
+        let (initiator_static, responder_static) = if initiator {
+            (local_static_public_key, rs)
+        } else {
+            (rs, local_static_public_key)
+        };
+
+        for (tokens, static_public_key) in &[
+            (pattern.initiator_pre_message(), initiator_static),
+            (pattern.responder_pre_message(), responder_static)
+        ] {
+            for token in tokens.iter() {
+                match (token, static_public_key) {
+                    (Token::S, Some(public_key)) => symmetric_state.mix_hash(public_key.as_ref()),
+                    _ => return Err(NoiseError::MissingKey),
+                }
+            }
+        }
+
+        Ok(HandshakeState {
+            symmetric_state,
+            e_public_key: e.public_key()?,
+            pattern,
+            initiator,
+            s,
+            e,
+            rs,
+            re: None,
+            psk,
+            message_index: 0,
+        })
+    }
+
+    pub fn is_initiator(&self) -> bool {
+        self.initiator
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.message_index == self.pattern.messages().len()
+    }
+
+    pub fn is_my_turn(&self) -> bool {
+        !self.is_finished() && self.message_index.is_multiple_of(2) == self.initiator
+    }
+
+    pub fn message_index(&self) -> usize {
+        self.message_index
+    }
+
+    pub fn local_ephemeral_public_key(&self) -> &PublicKey {
+        &self.e_public_key
+    }
+
+    pub fn remote_static_public_key(&self) -> Option<PublicKey> {
+        self.rs
+    }
+
+    pub fn handshake_hash(&self) -> [u8; HASH_SIZE] {
+        self.symmetric_state.handshake_hash()
+    }
+
+    fn dh(&self, local_static: bool, remote_static: bool) -> Result<SharedKey, NoiseError> {
+        let remote = if remote_static { self.rs } else { self.re }.ok_or(NoiseError::MissingKey)?;
+
+        if local_static {
+            let (private_key, _) = self.s.as_ref().ok_or(NoiseError::MissingKey)?;
+
+            Ok(SharedKey::derive(private_key, &remote)?)
+        } else {
+            Ok(self.e.diffie_hellman(&remote)?)
+        }
+    }
+
+    fn mix_dh(&mut self, token: Token) -> Result<(), NoiseError> {
+        let shared_key = match token {
+            Token::EE => self.dh(false, false)?,
+            Token::ES if self.initiator => self.dh(false, true)?,
+            Token::ES => self.dh(true, false)?,
+            Token::SE if self.initiator => self.dh(true, false)?,
+            Token::SE => self.dh(false, true)?,
+            Token::SS => self.dh(true, true)?,
+            _ => unreachable!(),
+        };
+
+        self.symmetric_state.mix_key(shared_key.as_ref())
+    }
+
+    pub fn write_message(&mut self, payload: &[u8]) -> Result<Vec<u8>, NoiseError> {
+        if self.is_finished() {
+            return Err(NoiseError::HandshakeFinished);
+        }
+        if !self.is_my_turn() {
+            return Err(NoiseError::UnexpectedMessage);
+        }
+
+        let mut message = Vec::new();
+
+        for token in self.pattern.messages()[self.message_index].clone() {
+            match token {
+                Token::E => {
+                    let e_public_key = self.e_public_key;
+
+                    message.extend_from_slice(e_public_key.as_ref());
+                    self.symmetric_state.mix_hash(e_public_key.as_ref());
+                    if self.pattern.is_psk() {
+                        self.symmetric_state.mix_key(e_public_key.as_ref())?;
+                    }
+                },
+                Token::S => {
+                    let (_, public_key) = self.s.ok_or(NoiseError::MissingKey)?;
+
+                    message.extend(self.symmetric_state.encrypt_and_hash(public_key.as_ref())?);
+                },
+                Token::Psk => {
+                    let psk = self.psk.ok_or(NoiseError::MissingKey)?;
+
+                    self.symmetric_state.mix_key_and_hash(&psk)?;
+                },
+                token => self.mix_dh(token)?,
+            }
+        }
+
+        message.extend(self.symmetric_state.encrypt_and_hash(payload)?);
+        self.message_index += 1;
+
+        Ok(message)
+    }
+
+    pub fn read_message(&mut self, message: &[u8]) -> Result<Vec<u8>, NoiseError> {
+        if self.is_finished() {
+            return Err(NoiseError::HandshakeFinished);
+        }
+        if self.is_my_turn() {
+            return Err(NoiseError::UnexpectedMessage);
+        }
+
+        // Only these change while reading, they are restored when a message is rejected
+        let snapshot = (self.symmetric_state.clone(), self.rs, self.re, self.message_index);
+
+        let result = self.read_tokens(message);
+        if result.is_err() {
+            (self.symmetric_state, self.rs, self.re, self.message_index) = snapshot;
+        }
+
+        result
+    }
+
+    fn read_tokens(&mut self, mut message: &[u8]) -> Result<Vec<u8>, NoiseError> {
+        for token in self.pattern.messages()[self.message_index].clone() {
+            match token {
+                Token::E => {
+                    if message.len() < DH_SIZE {
+                        return Err(NoiseError::MessageTooShort);
+                    }
+                    let (re, rest) = message.split_at(DH_SIZE);
+                    message = rest;
+
+                    self.re = Some(PublicKey::from(re.to_vec()));
+                    self.symmetric_state.mix_hash(re);
+                    if self.pattern.is_psk() {
+                        self.symmetric_state.mix_key(re)?;
+                    }
+                },
+                Token::S => {
+                    let length = if self.symmetric_state.has_key() { DH_SIZE + TAG_SIZE } else { DH_SIZE };
+                    if message.len() < length {
+                        return Err(NoiseError::MessageTooShort);
+                    }
+                    let (rs, rest) = message.split_at(length);
+                    message = rest;
+
+                    self.rs = Some(PublicKey::from(self.symmetric_state.decrypt_and_hash(rs)?));
+                },
+                Token::Psk => {
+                    let psk = self.psk.ok_or(NoiseError::MissingKey)?;
+
+                    self.symmetric_state.mix_key_and_hash(&psk)?;
+                },
+                token => self.mix_dh(token)?,
+            }
+        }
+
+        let payload = self.symmetric_state.decrypt_and_hash(message)?;
+        self.message_index += 1;
+
+        Ok(payload)
+    }
+
+    // Returns the initiator to responder and responder to initiator cipher states
+    pub fn split(&self) -> Result<(CipherState, CipherState), NoiseError> {
+        if !self.is_finished() {
+            return Err(NoiseError::UnexpectedMessage);
+        }
+
+        self.symmetric_state.split()
+    }
+}
+
+impl Debug for HandshakeState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.debug_struct("HandshakeState")
+            .field("pattern", &self.pattern.name())
+            .field("initiator", &self.initiator)
+            .field("message_index", &self.message_index)
+            .finish()
+    }
+}
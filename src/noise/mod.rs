@@ -0,0 +1,244 @@
+mod noise_error;
+pub use noise_error::NoiseError;
+mod cipher_state;
+pub use cipher_state::CipherState;
+mod symmetric_state;
+pub use symmetric_state::SymmetricState;
+mod handshake_pattern;
+pub use handshake_pattern::{ HandshakePattern, Token };
+mod handshake_state;
+pub use handshake_state::HandshakeState;
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        x25519::{ PrivateKey, PublicKey, EphemeralSecret },
+        noise::{ HandshakePattern, HandshakeState, NoiseError },
+    };
+
+    // Vectors taken from the cacophony test suite
+    struct Vector {
+        protocol_name: &'static str,
+        prologue: &'static str,
+        psk: Option<&'static str>,
+        init_static: &'static str,
+        init_ephemeral: &'static str,
+        init_remote_static: Option<&'static str>,
+        resp_static: &'static str,
+        resp_ephemeral: &'static str,
+        handshake_hash: &'static str,
+        messages: &'static [(&'static str, &'static str)],
+    }
+
+    const IK: Vector = Vector {
+        protocol_name: "Noise_IK_25519_ChaChaPoly_SHA256",
+        prologue: "4a6f686e2047616c74",
+        psk: None,
+        init_static: "e61ef9919cde45dd5f82166404bd08e38bceb5dfdfded0a34c8df7ed542214d1",
+        init_ephemeral: "893e28b9dc6ca8d611ab664754b8ceb7bac5117349a4439a6b0569da977c464a",
+        init_remote_static: Some("31e0303fd6418d2f8c0e78b91f22e8caed0fbe48656dcf4767e4834f701b8f62"),
+        resp_static: "4a3acbfdb163dec651dfa3194dece676d437029c62a408b4c5ea9114246e4893",
+        resp_ephemeral: "bbdb4cdbd309f1a1f2e1456967fe288cadd6f712d65dc7b7793d5e63da6b375b",
+        handshake_hash: "0b0f68fb0c27e03ce9b97565995ed4838cc0581b762ef72b062f6a546419fad7",
+        messages: &[
+            ("4c756477696720766f6e204d69736573", "ca35def5ae56cec33dc2036731ab14896bc4c75dbb07a61f879f8e3afa4c7944718da798efbcd91528520204f904b9bd6c7413dccdc214d951e15253e39987f18146e8cd0873654207148333479d4d16c289f0294b29960a72f48e0b7bba2e89083169825e59642148d492020664ccf7"),
+            ("4d757272617920526f746862617264", "95ebc60d2b1fa672c1f46a8aa265ef51bfe38e7ccb39ec5be34069f1448088435361e70b2ed446e6c9ec387d1d6b3b840f194e373979d241b203c4acafccf5"),
+            ("462e20412e20486179656b", "050e9f3c8fac16b68dbce8f8c4bfbf6617c897f9ada4aa29aa19c8"),
+            ("4361726c204d656e676572", "344233a6cabb7141d80f3da2fedc311d9646bbb0f505afe403a667"),
+            ("4a65616e2d426170746973746520536179", "62cdeeb172ad7ade7aa7d9e069da5790f12331bfa00177787a1d0810c67dc3b2b4"),
+            ("457567656e2042f6686d20766f6e2042617765726b", "029bead1b40992327044d409d9a1f3ad8f36c3c452775d557e18bbeb2e8dfcead32d514024"),
+        ],
+    };
+
+    const IK_PSK2: Vector = Vector {
+        protocol_name: "Noise_IKpsk2_25519_ChaChaPoly_SHA256",
+        prologue: "4a6f686e2047616c74",
+        psk: Some("54686973206973206d7920417573747269616e20706572737065637469766521"),
+        init_static: "e61ef9919cde45dd5f82166404bd08e38bceb5dfdfded0a34c8df7ed542214d1",
+        init_ephemeral: "893e28b9dc6ca8d611ab664754b8ceb7bac5117349a4439a6b0569da977c464a",
+        init_remote_static: Some("31e0303fd6418d2f8c0e78b91f22e8caed0fbe48656dcf4767e4834f701b8f62"),
+        resp_static: "4a3acbfdb163dec651dfa3194dece676d437029c62a408b4c5ea9114246e4893",
+        resp_ephemeral: "bbdb4cdbd309f1a1f2e1456967fe288cadd6f712d65dc7b7793d5e63da6b375b",
+        handshake_hash: "8310f86394dc0dabb40beb8210031556db4403ab1202db7034c526232147a700",
+        messages: &[
+            ("4c756477696720766f6e204d69736573", "ca35def5ae56cec33dc2036731ab14896bc4c75dbb07a61f879f8e3afa4c79442ec9b09893d0f510791784c10cbc959f25b1766e0def6e301d14fbca1c7790ac829b8b3674f5f649a5f0e98479662cbfbf2b2c47cd4b09fcd266cd29d7cb675f1808849707847840f6d178ec4d3733aa"),
+            ("4d757272617920526f746862617264", "95ebc60d2b1fa672c1f46a8aa265ef51bfe38e7ccb39ec5be34069f1448088439a1b3cebf680b2c74217fcb5eba4ff58a9468cd90c4aca6194f57479b379a7"),
+            ("462e20412e20486179656b", "a8fde7a0accec190cd306c5950d4fd8e04a205ec288aa747d8b347"),
+            ("4361726c204d656e676572", "59caddd9984a3bbe24c4fb31a2bd455b7eba3fa0980674b1a3a5f9"),
+            ("4a65616e2d426170746973746520536179", "3b9bfebd210c22ba0cff9de79b4007d7a552fffbf92616881faa8a883e25b80258"),
+            ("457567656e2042f6686d20766f6e2042617765726b", "f37512df1043d564d7c46ac85c53d3b6a9a05724bc297e7142808f217561651217fe85b782"),
+        ],
+    };
+
+    const XX: Vector = Vector {
+        protocol_name: "Noise_XX_25519_ChaChaPoly_SHA256",
+        prologue: "4a6f686e2047616c74",
+        psk: None,
+        init_static: "e61ef9919cde45dd5f82166404bd08e38bceb5dfdfded0a34c8df7ed542214d1",
+        init_ephemeral: "893e28b9dc6ca8d611ab664754b8ceb7bac5117349a4439a6b0569da977c464a",
+        init_remote_static: None,
+        resp_static: "4a3acbfdb163dec651dfa3194dece676d437029c62a408b4c5ea9114246e4893",
+        resp_ephemeral: "bbdb4cdbd309f1a1f2e1456967fe288cadd6f712d65dc7b7793d5e63da6b375b",
+        handshake_hash: "c8e5f64e846193be2a834104c2a009868d6c9f3bd3c186299888b488b2f1f58e",
+        messages: &[
+            ("4c756477696720766f6e204d69736573", "ca35def5ae56cec33dc2036731ab14896bc4c75dbb07a61f879f8e3afa4c79444c756477696720766f6e204d69736573"),
+            ("4d757272617920526f746862617264", "95ebc60d2b1fa672c1f46a8aa265ef51bfe38e7ccb39ec5be34069f14480884381cbad1f276e038c48378ffce2b65285e08d6b68aaa3629a5a8639392490e5b9bd5269c2f1e4f488ed8831161f19b7815528f8982ffe09be9b5c412f8a0db50f8814c7194e83f23dbd8d162c9326ad"),
+            ("462e20412e20486179656b", "c7195ffacac1307ff99046f219750fc47693e23c3cb08b89c2af808b444850a80ae475b9df0f169ae80a89be0865b57f58c9fea0d4ec82a286427402f113e4b6ae769a1d95941d49b25030"),
+            ("4361726c204d656e676572", "96763ed773f8e47bb3712f0e29b3060ffc956ffc146cee53d5e1df"),
+            ("4a65616e2d426170746973746520536179", "3e40f15f6f3a46ae446b253bf8b1d9ffb6ed9b174d272328ff91a7e2e5c79c07f5"),
+            ("457567656e2042f6686d20766f6e2042617765726b", "eb3f3515110702e047a6c9da4478b6ead94873c11c0f2d710ddb3f09fce024b3a58502ae3f"),
+        ],
+    };
+
+    const XX_PSK3: Vector = Vector {
+        protocol_name: "Noise_XXpsk3_25519_ChaChaPoly_SHA256",
+        prologue: "4a6f686e2047616c74",
+        psk: Some("54686973206973206d7920417573747269616e20706572737065637469766521"),
+        init_static: "e61ef9919cde45dd5f82166404bd08e38bceb5dfdfded0a34c8df7ed542214d1",
+        init_ephemeral: "893e28b9dc6ca8d611ab664754b8ceb7bac5117349a4439a6b0569da977c464a",
+        init_remote_static: None,
+        resp_static: "4a3acbfdb163dec651dfa3194dece676d437029c62a408b4c5ea9114246e4893",
+        resp_ephemeral: "bbdb4cdbd309f1a1f2e1456967fe288cadd6f712d65dc7b7793d5e63da6b375b",
+        handshake_hash: "a477edf6a131bbdb54707f6ea30eab6cd935d9b560f0e5fd1f053a95a99669fb",
+        messages: &[
+            ("4c756477696720766f6e204d69736573", "ca35def5ae56cec33dc2036731ab14896bc4c75dbb07a61f879f8e3afa4c7944c9f5ff0e8079630cb7e270c20bbf480821b77a384a645c71a2fd9b3db1c16a5f"),
+            ("4d757272617920526f746862617264", "95ebc60d2b1fa672c1f46a8aa265ef51bfe38e7ccb39ec5be34069f144808843b123def17f71e6ae8e57e0e1dec5949c5f7415c6f33517398747d821a06dc23ad430aa1fd7381d46195c378a819fd574425462cbb2d4ca339e738a0b7001dc91423fbf55a99af0c6f1df21012ceb2f"),
+            ("462e20412e20486179656b", "52187316111b118d4c060364f7b975dc0809b2590779aff2d63113c564f11744493384db7bf32d5ae6686df6ab06d508d2e07caaf1d6afc010b978735fc78900e71ae1d314130d042e729a"),
+            ("4361726c204d656e676572", "eaedc672d4c21e0e2955758756fb98f194c4e90d5deb5b6cf30b27"),
+            ("4a65616e2d426170746973746520536179", "522d543c5fe799d09a3d9da7ff54d0dc03c8af1dc7751d2ff708339d2290943e98"),
+            ("457567656e2042f6686d20766f6e2042617765726b", "7d4e2c3873eef6a213b04e72f9df60a91666072d3544c5d96c34a09e2329b5030bee796741"),
+        ],
+    };
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+
+    fn key(hex: &str) -> [u8; 32] {
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&from_hex(hex));
+
+        key
+    }
+
+    fn static_key_pair(hex: &str) -> (PrivateKey, PublicKey) {
+        let secret = from_hex(hex);
+
+        (PrivateKey::new(&secret).unwrap(), PublicKey::new(&secret).unwrap())
+    }
+
+    fn run_vector(pattern: HandshakePattern, vector: &Vector) {
+        assert_eq!(pattern.protocol_name(), vector.protocol_name);
+        let pattern_length = pattern.messages().len();
+
+        let prologue = from_hex(vector.prologue);
+        let psk = vector.psk.map(key);
+
+        let mut initiator = HandshakeState::new(
+            pattern.clone(),
+            true,
+            &prologue,
+            Some(static_key_pair(vector.init_static)),
+            EphemeralSecret::from(key(vector.init_ephemeral)),
+            vector.init_remote_static.map(|hex| PublicKey::from(from_hex(hex))),
+            psk
+        ).unwrap();
+        let mut responder = HandshakeState::new(
+            pattern,
+            false,
+            &prologue,
+            Some(static_key_pair(vector.resp_static)),
+            EphemeralSecret::from(key(vector.resp_ephemeral)),
+            None,
+            psk
+        ).unwrap();
+
+        let mut messages = vector.messages.iter();
+
+        while !initiator.is_finished() {
+            let (payload, ciphertext) = messages.next().unwrap();
+            let (sender, receiver) = if initiator.is_my_turn() {
+                (&mut initiator, &mut responder)
+            } else {
+                (&mut responder, &mut initiator)
+            };
+
+            let message = sender.write_message(&from_hex(payload)).unwrap();
+            assert_eq!(message, from_hex(ciphertext));
+            assert_eq!(receiver.read_message(&message).unwrap(), from_hex(payload));
+        }
+
+        assert!(responder.is_finished());
+        assert_eq!(initiator.handshake_hash().to_vec(), from_hex(vector.handshake_hash));
+        assert_eq!(responder.handshake_hash().to_vec(), from_hex(vector.handshake_hash));
+
+        let (mut initiator_send, mut initiator_receive) = initiator.split().unwrap();
+        let (mut responder_receive, mut responder_send) = responder.split().unwrap();
+
+        // Senders keep alternating after the handshake, starting from whoever did not send the last handshake message
+        for (index, (payload, ciphertext)) in messages.enumerate().map(|(index, message)| (index + pattern_length, message)) {
+            let (sender, receiver) = if index.is_multiple_of(2) {
+                (&mut initiator_send, &mut responder_receive)
+            } else {
+                (&mut responder_send, &mut initiator_receive)
+            };
+
+            let message = sender.encrypt_with_ad(&[], &from_hex(payload)).unwrap();
+            assert_eq!(message, from_hex(ciphertext));
+            assert_eq!(receiver.decrypt_with_ad(&[], &message).unwrap(), from_hex(payload));
+        }
+    }
+
+    #[test]
+    fn noise_ik() {
+        run_vector(HandshakePattern::ik(), &IK);
+    }
+
+    #[test]
+    fn noise_ik_psk2() {
+        run_vector(HandshakePattern::ik().with_psk(2), &IK_PSK2);
+    }
+
+    #[test]
+    fn noise_xx() {
+        run_vector(HandshakePattern::xx(), &XX);
+    }
+
+    #[test]
+    fn noise_xx_psk3() {
+        run_vector(HandshakePattern::xx().with_psk(3), &XX_PSK3);
+    }
+
+    #[test]
+    fn tampered_message_is_rejected() {
+        let prologue = from_hex(IK.prologue);
+
+        let mut initiator = HandshakeState::new(
+            HandshakePattern::ik(),
+            true,
+            &prologue,
+            Some(static_key_pair(IK.init_static)),
+            EphemeralSecret::from(key(IK.init_ephemeral)),
+            IK.init_remote_static.map(|hex| PublicKey::from(from_hex(hex))),
+            None
+        ).unwrap();
+        let mut responder = HandshakeState::new(
+            HandshakePattern::ik(),
+            false,
+            &prologue,
+            Some(static_key_pair(IK.resp_static)),
+            EphemeralSecret::from(key(IK.resp_ephemeral)),
+            None,
+            None
+        ).unwrap();
+
+        let message = initiator.write_message(&[]).unwrap();
+        let mut tampered = message.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+
+        assert_eq!(responder.read_message(&tampered), Err(NoiseError::Decryption));
+        assert_eq!(responder.message_index(), 0);
+        assert_eq!(responder.read_message(&message), Ok(vec![]));
+    }
+}
@@ -0,0 +1,39 @@
+use std::fmt::{ Formatter, Display, Error };
+use crate::x25519::CryptoError;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NoiseError {
+    Crypto(CryptoError),
+    KeyDerivation,
+    Encryption,
+    Decryption,
+    MessageTooShort,
+    MissingKey,
+    UnexpectedMessage,
+    HandshakeFinished,
+    NonceExhausted,
+}
+
+impl Display for NoiseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            NoiseError::Crypto(error) => write!(f, "{}", error),
+            NoiseError::KeyDerivation => f.write_str("failed to derive handshake keys"),
+            NoiseError::Encryption => f.write_str("failed to encrypt message"),
+            NoiseError::Decryption => f.write_str("failed to decrypt message"),
+            NoiseError::MessageTooShort => f.write_str("handshake message too short"),
+            NoiseError::MissingKey => f.write_str("handshake pattern requires a key that was not provided"),
+            NoiseError::UnexpectedMessage => f.write_str("handshake message received out of turn"),
+            NoiseError::HandshakeFinished => f.write_str("handshake already finished"),
+            NoiseError::NonceExhausted => f.write_str("cipher nonce exhausted"),
+        }
+    }
+}
+
+impl std::error::Error for NoiseError {}
+
+impl From<CryptoError> for NoiseError {
+    fn from(error: CryptoError) -> Self {
+        NoiseError::Crypto(error)
+    }
+}
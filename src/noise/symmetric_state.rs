@@ -0,0 +1,110 @@
+use openssl::sha::Sha256;
+use crate::{
+    hkdf::{ self, HASH_SIZE },
+    noise::{
+        CipherState,
+        NoiseError,
+        cipher_state::KEY_SIZE,
+    },
+};
+
+#[derive(Clone)]
+pub struct SymmetricState {
+    cipher_state: CipherState,
+    ck: [u8; HASH_SIZE],
+    h: [u8; HASH_SIZE],
+}
+
+impl SymmetricState {
+    pub fn new(protocol_name: &str) -> Self {
+        let mut h = [0u8; HASH_SIZE];
+
+        if protocol_name.len() <= HASH_SIZE {
+            h[..protocol_name.len()].copy_from_slice(protocol_name.as_bytes());
+        } else {
+            let mut sha256 = Sha256::new();
+            sha256.update(protocol_name.as_bytes());
+            h = sha256.finish();
+        }
+
+        SymmetricState {
+            cipher_state: CipherState::new(None),
+            ck: h,
+            h,
+        }
+    }
+
+    fn hkdf(&self, ikm: &[u8], output: &mut [u8]) -> Result<(), NoiseError> {
+        let prk = hkdf::extract(&self.ck, ikm).map_err(|_| NoiseError::KeyDerivation)?;
+        hkdf::expand(&prk, &[], output).map_err(|_| NoiseError::KeyDerivation)
+    }
+
+    pub fn mix_key(&mut self, ikm: &[u8]) -> Result<(), NoiseError> {
+        let mut output = [0u8; 2 * HASH_SIZE];
+        self.hkdf(ikm, &mut output)?;
+
+        self.ck.copy_from_slice(&output[..HASH_SIZE]);
+
+        let mut k = [0u8; KEY_SIZE];
+        k.copy_from_slice(&output[HASH_SIZE..HASH_SIZE + KEY_SIZE]);
+        self.cipher_state = CipherState::new(Some(k));
+
+        Ok(())
+    }
+
+    pub fn mix_hash(&mut self, data: &[u8]) {
+        let mut sha256 = Sha256::new();
+        sha256.update(&self.h);
+        sha256.update(data);
+
+        self.h = sha256.finish();
+    }
+
+    pub fn mix_key_and_hash(&mut self, ikm: &[u8]) -> Result<(), NoiseError> {
+        let mut output = [0u8; 3 * HASH_SIZE];
+        self.hkdf(ikm, &mut output)?;
+
+        self.ck.copy_from_slice(&output[..HASH_SIZE]);
+        self.mix_hash(&output[HASH_SIZE..2 * HASH_SIZE]);
+
+        let mut k = [0u8; KEY_SIZE];
+        k.copy_from_slice(&output[2 * HASH_SIZE..2 * HASH_SIZE + KEY_SIZE]);
+        self.cipher_state = CipherState::new(Some(k));
+
+        Ok(())
+    }
+
+    pub fn has_key(&self) -> bool {
+        self.cipher_state.has_key()
+    }
+
+    pub fn handshake_hash(&self) -> [u8; HASH_SIZE] {
+        self.h
+    }
+
+    pub fn encrypt_and_hash(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, NoiseError> {
+        let ciphertext = self.cipher_state.encrypt_with_ad(&self.h, plaintext)?;
+        self.mix_hash(&ciphertext);
+
+        Ok(ciphertext)
+    }
+
+    pub fn decrypt_and_hash(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, NoiseError> {
+        let plaintext = self.cipher_state.decrypt_with_ad(&self.h, ciphertext)?;
+        self.mix_hash(ciphertext);
+
+        Ok(plaintext)
+    }
+
+    pub fn split(&self) -> Result<(CipherState, CipherState), NoiseError> {
+        let mut output = [0u8; 2 * HASH_SIZE];
+        self.hkdf(&[], &mut output)?;
+
+        let mut k1 = [0u8; KEY_SIZE];
+        k1.copy_from_slice(&output[..KEY_SIZE]);
+        let mut k2 = [0u8; KEY_SIZE];
+        k2.copy_from_slice(&output[HASH_SIZE..HASH_SIZE + KEY_SIZE]);
+
+        Ok((CipherState::new(Some(k1)), CipherState::new(Some(k2))))
+    }
+}
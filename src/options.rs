@@ -0,0 +1,151 @@
+use std::{
+    str::FromStr,
+    time::Duration,
+};
+use crate::instance::{ InstanceBuilder, CongestionControl, Capability, Capabilities, PaddingPolicy };
+
+pub const USAGE: &str = "Usage: chat-test [options]
+
+  --control-address <address>
+  --protocol-address <address>          address to exchange packets on, 0.0.0.0:6555 by default
+  --rekey-after-messages <count>
+  --rekey-after-bytes <count>
+  --rekey-after <seconds>
+  --session-lifetime <seconds>          a new handshake is made after this
+  --handshake-retransmit-interval <seconds>
+  --handshake-timeout <seconds>
+  --keepalive-interval <seconds>
+  --idle-timeout <seconds>
+  --congestion-control <new_reno|cubic>
+  --capabilities <list>                 comma separated from reliable_delivery, fragmentation and
+                                        path_mtu_discovery, or none
+  --busy-threshold <handshakes per second>
+  --obfuscation                         peers have to enable this as well
+  --padding <none|power_of_two|block:<size>|random:<max>>";
+
+fn parse_with<T>(option: &str, value: Option<String>, parse: fn(&str) -> Option<T>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", option))?;
+
+    parse(&value).ok_or_else(|| format!("Invalid value for {}: {}", option, value))
+}
+
+fn parse<T: FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    parse_with(option, value, |value| value.parse().ok())
+}
+
+fn parse_duration(option: &str, value: Option<String>) -> Result<Duration, String> {
+    let seconds: f64 = parse(option, value)?;
+    if !seconds.is_finite() || seconds < 0.0 {
+        return Err(format!("Invalid value for {}: {}", option, seconds));
+    }
+
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+fn parse_congestion_control(value: &str) -> Option<CongestionControl> {
+    match value {
+        "new_reno" => Some(CongestionControl::NewReno),
+        "cubic" => Some(CongestionControl::Cubic),
+        _ => None,
+    }
+}
+
+fn parse_capabilities(value: &str) -> Option<Capabilities> {
+    if value == "none" {
+        return Some(Capabilities::default());
+    }
+
+    value.split(',').try_fold(Capabilities::default(), |capabilities, capability| {
+        let capability = match capability {
+            "reliable_delivery" => Capability::ReliableDelivery,
+            "fragmentation" => Capability::Fragmentation,
+            "path_mtu_discovery" => Capability::PathMtuDiscovery,
+            _ => return None,
+        };
+
+        Some(capabilities.with(capability))
+    })
+}
+
+fn parse_padding_policy(value: &str) -> Option<PaddingPolicy> {
+    let (name, size) = match value.split_once(':') {
+        Some((name, size)) => (name, Some(size.parse().ok()?)),
+        None => (value, None),
+    };
+
+    match (name, size) {
+        ("none", None) => Some(PaddingPolicy::None),
+        ("power_of_two", None) => Some(PaddingPolicy::PowerOfTwo),
+        ("block", Some(size)) if size > 0 => Some(PaddingPolicy::Block(size)),
+        ("random", Some(max)) => Some(PaddingPolicy::Random(max)),
+        _ => None,
+    }
+}
+
+// Every option maps to one of the builder's setters
+pub fn instance_builder(mut args: impl Iterator<Item = String>) -> Result<InstanceBuilder, String> {
+    let mut instance_builder = InstanceBuilder::new();
+
+    while let Some(option) = args.next() {
+        instance_builder = match option.as_str() {
+            "--control-address" => instance_builder.set_control_address(parse(&option, args.next())?),
+            "--protocol-address" => instance_builder.set_protocol_address(parse(&option, args.next())?),
+            "--rekey-after-messages" => instance_builder.set_rekey_after_messages(parse(&option, args.next())?),
+            "--rekey-after-bytes" => instance_builder.set_rekey_after_bytes(parse(&option, args.next())?),
+            "--rekey-after" => instance_builder.set_rekey_after_time(parse_duration(&option, args.next())?),
+            "--session-lifetime" => instance_builder.set_session_lifetime(parse_duration(&option, args.next())?),
+            "--handshake-retransmit-interval" => instance_builder.set_handshake_retransmit_interval(parse_duration(&option, args.next())?),
+            "--handshake-timeout" => instance_builder.set_handshake_timeout(parse_duration(&option, args.next())?),
+            "--keepalive-interval" => instance_builder.set_keepalive_interval(parse_duration(&option, args.next())?),
+            "--idle-timeout" => instance_builder.set_idle_timeout(parse_duration(&option, args.next())?),
+            "--busy-threshold" => instance_builder.set_busy_threshold(parse(&option, args.next())?),
+            "--congestion-control" => instance_builder.set_congestion_control(parse_with(&option, args.next(), parse_congestion_control)?),
+            "--capabilities" => instance_builder.set_capabilities(parse_with(&option, args.next(), parse_capabilities)?),
+            "--padding" => instance_builder.set_padding_policy(parse_with(&option, args.next(), parse_padding_policy)?),
+            "--obfuscation" => instance_builder.set_obfuscation(true),
+            _ => return Err(format!("Unknown option {}", option)),
+        };
+    }
+
+    Ok(instance_builder)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        options::{ instance_builder, parse_capabilities, parse_padding_policy },
+        instance::{ Capability, Capabilities, PaddingPolicy },
+    };
+
+    fn args(args: &str) -> impl Iterator<Item = String> + '_ {
+        args.split(' ').filter(|arg| !arg.is_empty()).map(String::from)
+    }
+
+    #[test]
+    fn options_are_validated() {
+        assert!(instance_builder(args("")).is_ok());
+        assert!(instance_builder(args("--obfuscation --rekey-after 1.5 --padding block:256 --protocol-address 127.0.0.1:6555")).is_ok());
+
+        assert_eq!(instance_builder(args("--verbose")).err(), Some("Unknown option --verbose".to_string()));
+        assert_eq!(instance_builder(args("--idle-timeout")).err(), Some("--idle-timeout needs a value".to_string()));
+        assert_eq!(instance_builder(args("--idle-timeout -1")).err(), Some("Invalid value for --idle-timeout: -1".to_string()));
+        assert_eq!(instance_builder(args("--busy-threshold many")).err(), Some("Invalid value for --busy-threshold: many".to_string()));
+        assert_eq!(instance_builder(args("--congestion-control vegas")).err(), Some("Invalid value for --congestion-control: vegas".to_string()));
+    }
+
+    #[test]
+    fn capabilities_and_padding_are_parsed() {
+        assert_eq!(parse_capabilities("none"), Some(Capabilities::default()));
+        assert_eq!(
+            parse_capabilities("fragmentation,path_mtu_discovery"),
+            Some(Capabilities::default().with(Capability::Fragmentation).with(Capability::PathMtuDiscovery))
+        );
+        assert_eq!(parse_capabilities("fragmentation,teleportation"), None);
+
+        assert_eq!(parse_padding_policy("power_of_two"), Some(PaddingPolicy::PowerOfTwo));
+        assert_eq!(parse_padding_policy("random:64"), Some(PaddingPolicy::Random(64)));
+        assert_eq!(parse_padding_policy("block:0"), None);
+        assert_eq!(parse_padding_policy("block"), None);
+        assert_eq!(parse_padding_policy("none:5"), None);
+    }
+}
@@ -0,0 +1,273 @@
+use std::{
+    fmt::{ Formatter, Debug, Error },
+    collections::{ HashMap, VecDeque },
+};
+use rand::prelude::ThreadRng;
+use openssl::symm::{ Cipher, encrypt_aead, decrypt_aead };
+use crate::{
+    hkdf::{ self, HASH_SIZE },
+    x25519::{ PublicKey, EphemeralSecret },
+    ratchet::{ Header, RatchetError },
+};
+
+const ROOT_LABEL: &[u8] = b"chat-test ratchet root";
+const MESSAGE_KEY_LABEL: &[u8] = b"chat-test ratchet message key";
+const TAG_SIZE: usize = 16;
+
+// Limits on how many message keys a peer can make us derive and store ahead of time
+pub const MAX_SKIP: u32 = 1000;
+pub const MAX_SKIPPED_MESSAGE_KEYS: usize = 2000;
+
+type Key = [u8; HASH_SIZE];
+
+fn kdf_rk(root_key: &Key, dh_output: &[u8]) -> Result<(Key, Key), RatchetError> {
+    let prk = hkdf::extract(root_key, dh_output).map_err(|_| RatchetError::KeyDerivation)?;
+
+    let mut output = [0u8; 2 * HASH_SIZE];
+    hkdf::expand(&prk, ROOT_LABEL, &mut output).map_err(|_| RatchetError::KeyDerivation)?;
+
+    let mut root_key = [0u8; HASH_SIZE];
+    root_key.copy_from_slice(&output[..HASH_SIZE]);
+    let mut chain_key = [0u8; HASH_SIZE];
+    chain_key.copy_from_slice(&output[HASH_SIZE..]);
+
+    Ok((root_key, chain_key))
+}
+
+fn kdf_ck(chain_key: &Key) -> Result<(Key, Key), RatchetError> {
+    let message_key = hkdf::hmac(chain_key, &[&[0x01]]).map_err(|_| RatchetError::KeyDerivation)?;
+    let chain_key = hkdf::hmac(chain_key, &[&[0x02]]).map_err(|_| RatchetError::KeyDerivation)?;
+
+    Ok((chain_key, message_key))
+}
+
+fn message_cipher(message_key: &Key) -> Result<([u8; 32], [u8; 12]), RatchetError> {
+    let mut output = [0u8; 44];
+    hkdf::expand(message_key, MESSAGE_KEY_LABEL, &mut output).map_err(|_| RatchetError::KeyDerivation)?;
+
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&output[..32]);
+    let mut nonce = [0u8; 12];
+    nonce.copy_from_slice(&output[32..]);
+
+    Ok((key, nonce))
+}
+
+fn associated_data(ad: &[u8], header: &Header) -> Vec<u8> {
+    let mut associated_data = ad.to_vec();
+    associated_data.extend(bincode::serialize(header).unwrap());
+
+    associated_data
+}
+
+// Derives the keys of messages `from..until` of a receiving chain and returns the chain key of
+// message `until`
+fn skip_message_keys(mut chain_key: Key, dh_remote: PublicKey, from: u32, until: u32, skipped: &mut Vec<((PublicKey, u32), Key)>) -> Result<Key, RatchetError> {
+    if until > from.saturating_add(MAX_SKIP) {
+        return Err(RatchetError::TooManySkippedMessages);
+    }
+
+    for message_number in from..until {
+        let (next_chain_key, message_key) = kdf_ck(&chain_key)?;
+        chain_key = next_chain_key;
+
+        skipped.push(((dh_remote, message_number), message_key));
+    }
+
+    Ok(chain_key)
+}
+
+// A DH ratchet step, only applied once a message of the new receiving chain decrypts
+struct RatchetStep {
+    dh_self: EphemeralSecret,
+    dh_self_public_key: PublicKey,
+    dh_remote: PublicKey,
+    root_key: Key,
+    receiving_chain_key: Key,
+    sending_chain_key: Key,
+}
+
+pub struct DoubleRatchet {
+    dh_self: EphemeralSecret,
+    dh_self_public_key: PublicKey,
+    dh_remote: Option<PublicKey>,
+    root_key: Key,
+    sending_chain_key: Option<Key>,
+    receiving_chain_key: Option<Key>,
+    sending_message_number: u32,
+    receiving_message_number: u32,
+    previous_chain_length: u32,
+    skipped_message_keys: HashMap<(PublicKey, u32), Key>,
+    skipped_order: VecDeque<(PublicKey, u32)>,
+}
+
+impl DoubleRatchet {
+    // The initiator knows the responder's ratchet public key from the handshake and can send right away
+    pub fn new_initiator(root_key: Key, dh_self: EphemeralSecret, dh_remote: PublicKey) -> Result<Self, RatchetError> {
+        let dh_output = dh_self.diffie_hellman(&dh_remote)?;
+        let (root_key, sending_chain_key) = kdf_rk(&root_key, dh_output.as_ref())?;
+
+        Ok(DoubleRatchet {
+            dh_self_public_key: dh_self.public_key()?,
+            dh_self,
+            dh_remote: Some(dh_remote),
+            root_key,
+            sending_chain_key: Some(sending_chain_key),
+            receiving_chain_key: None,
+            sending_message_number: 0,
+            receiving_message_number: 0,
+            previous_chain_length: 0,
+            skipped_message_keys: HashMap::new(),
+            skipped_order: VecDeque::new(),
+        })
+    }
+
+    // The responder also learned the initiator's ratchet public key during the handshake, so it
+    // performs the first DH ratchet step immediately instead of waiting for the first message
+    pub fn new_responder(rng: &mut ThreadRng, root_key: Key, dh_self: EphemeralSecret, dh_remote: PublicKey) -> Result<Self, RatchetError> {
+        let mut double_ratchet = DoubleRatchet {
+            dh_self_public_key: dh_self.public_key()?,
+            dh_self,
+            dh_remote: None,
+            root_key,
+            sending_chain_key: None,
+            receiving_chain_key: None,
+            sending_message_number: 0,
+            receiving_message_number: 0,
+            previous_chain_length: 0,
+            skipped_message_keys: HashMap::new(),
+            skipped_order: VecDeque::new(),
+        };
+        let step = double_ratchet.dh_ratchet(rng, &dh_remote)?;
+        double_ratchet.apply(step);
+
+        Ok(double_ratchet)
+    }
+
+    pub fn encrypt(&mut self, plaintext: &[u8], ad: &[u8]) -> Result<(Header, Vec<u8>), RatchetError> {
+        let (chain_key, message_key) = kdf_ck(&self.sending_chain_key.ok_or(RatchetError::NoSendingChain)?)?;
+
+        let header = Header {
+            dh_public_key: self.dh_self_public_key,
+            previous_chain_length: self.previous_chain_length,
+            message_number: self.sending_message_number,
+        };
+
+        let (key, nonce) = message_cipher(&message_key)?;
+        let mut tag = [0u8; TAG_SIZE];
+        let mut ciphertext = encrypt_aead(Cipher::chacha20_poly1305(), &key, Some(&nonce), &associated_data(ad, &header), plaintext, &mut tag)
+            .map_err(|_| RatchetError::Encryption)?;
+        ciphertext.extend_from_slice(&tag);
+
+        self.sending_chain_key = Some(chain_key);
+        self.sending_message_number += 1;
+
+        Ok((header, ciphertext))
+    }
+
+    // Nothing changes until the message decrypts, so a forged or corrupted message cannot advance
+    // the ratchet
+    pub fn decrypt(&mut self, rng: &mut ThreadRng, header: &Header, ciphertext: &[u8], ad: &[u8]) -> Result<Vec<u8>, RatchetError> {
+        let index = (header.dh_public_key, header.message_number);
+        if let Some(message_key) = self.skipped_message_keys.get(&index) {
+            let plaintext = Self::open(message_key, header, ciphertext, ad)?;
+            self.skipped_message_keys.remove(&index);
+            self.skipped_order.retain(|skipped| skipped != &index);
+
+            return Ok(plaintext);
+        }
+
+        let mut skipped = Vec::new();
+        let (step, chain_key, from) = if self.dh_remote != Some(header.dh_public_key) {
+            if let (Some(chain_key), Some(dh_remote)) = (self.receiving_chain_key, self.dh_remote) {
+                skip_message_keys(chain_key, dh_remote, self.receiving_message_number, header.previous_chain_length, &mut skipped)?;
+            }
+            let step = self.dh_ratchet(rng, &header.dh_public_key)?;
+            let chain_key = step.receiving_chain_key;
+
+            (Some(step), chain_key, 0)
+        } else {
+            (None, self.receiving_chain_key.ok_or(RatchetError::Decryption)?, self.receiving_message_number)
+        };
+
+        let chain_key = skip_message_keys(chain_key, header.dh_public_key, from, header.message_number, &mut skipped)?;
+        let (chain_key, message_key) = kdf_ck(&chain_key)?;
+        let plaintext = Self::open(&message_key, header, ciphertext, ad)?;
+
+        if let Some(step) = step {
+            self.apply(step);
+        }
+        for (index, message_key) in skipped {
+            self.skipped_message_keys.insert(index, message_key);
+            self.skipped_order.push_back(index);
+            if self.skipped_order.len() > MAX_SKIPPED_MESSAGE_KEYS {
+                if let Some(oldest) = self.skipped_order.pop_front() {
+                    self.skipped_message_keys.remove(&oldest);
+                }
+            }
+        }
+        self.receiving_chain_key = Some(chain_key);
+        self.receiving_message_number = from.max(header.message_number) + 1;
+
+        Ok(plaintext)
+    }
+
+    fn open(message_key: &Key, header: &Header, ciphertext: &[u8], ad: &[u8]) -> Result<Vec<u8>, RatchetError> {
+        if ciphertext.len() < TAG_SIZE {
+            return Err(RatchetError::Decryption);
+        }
+        let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - TAG_SIZE);
+
+        let (key, nonce) = message_cipher(message_key)?;
+        decrypt_aead(Cipher::chacha20_poly1305(), &key, Some(&nonce), &associated_data(ad, header), ciphertext, tag)
+            .map_err(|_| RatchetError::Decryption)
+    }
+
+    fn dh_ratchet(&self, rng: &mut ThreadRng, dh_remote: &PublicKey) -> Result<RatchetStep, RatchetError> {
+        let (root_key, receiving_chain_key) = kdf_rk(&self.root_key, self.dh_self.diffie_hellman(dh_remote)?.as_ref())?;
+
+        let dh_self = EphemeralSecret::new(rng);
+        let dh_self_public_key = dh_self.public_key()?;
+        let (root_key, sending_chain_key) = kdf_rk(&root_key, dh_self.diffie_hellman(dh_remote)?.as_ref())?;
+
+        Ok(RatchetStep {
+            dh_self,
+            dh_self_public_key,
+            dh_remote: *dh_remote,
+            root_key,
+            receiving_chain_key,
+            sending_chain_key,
+        })
+    }
+
+    fn apply(&mut self, step: RatchetStep) {
+        self.previous_chain_length = self.sending_message_number;
+        self.sending_message_number = 0;
+        self.receiving_message_number = 0;
+        self.dh_remote = Some(step.dh_remote);
+        self.root_key = step.root_key;
+        self.receiving_chain_key = Some(step.receiving_chain_key);
+        self.sending_chain_key = Some(step.sending_chain_key);
+
+        // Replacing the old key pair erases its secret
+        self.dh_self = step.dh_self;
+        self.dh_self_public_key = step.dh_self_public_key;
+    }
+
+    #[cfg(test)]
+    pub fn skipped_message_keys(&self) -> usize {
+        self.skipped_message_keys.len()
+    }
+}
+
+impl Debug for DoubleRatchet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.debug_struct("DoubleRatchet")
+            .field("dh_self_public_key", &self.dh_self_public_key)
+            .field("dh_remote", &self.dh_remote)
+            .field("sending_message_number", &self.sending_message_number)
+            .field("receiving_message_number", &self.receiving_message_number)
+            .field("skipped_message_keys", &self.skipped_message_keys.len())
+            .finish()
+    }
+}
@@ -0,0 +1,9 @@
+use serde::{ Serialize, Deserialize };
+use crate::x25519::PublicKey;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub dh_public_key: PublicKey,
+    pub previous_chain_length: u32,
+    pub message_number: u32,
+}
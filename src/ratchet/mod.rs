@@ -0,0 +1,103 @@
+mod ratchet_error;
+pub use ratchet_error::RatchetError;
+mod header;
+pub use header::Header;
+mod double_ratchet;
+pub use double_ratchet::DoubleRatchet;
+
+#[cfg(test)]
+mod tests {
+    use rand::{ thread_rng, RngCore };
+    use crate::{
+        x25519::EphemeralSecret,
+        ratchet::{ DoubleRatchet, RatchetError },
+    };
+
+    fn pair() -> (DoubleRatchet, DoubleRatchet) {
+        let mut rng = thread_rng();
+
+        let mut root_key = [0u8; 32];
+        rng.fill_bytes(&mut root_key);
+
+        let alice_secret = EphemeralSecret::new(&mut rng);
+        let bob_secret = EphemeralSecret::new(&mut rng);
+        let alice_public_key = alice_secret.public_key().unwrap();
+        let bob_public_key = bob_secret.public_key().unwrap();
+
+        (
+            DoubleRatchet::new_initiator(root_key, alice_secret, bob_public_key).unwrap(),
+            DoubleRatchet::new_responder(&mut rng, root_key, bob_secret, alice_public_key).unwrap(),
+        )
+    }
+
+    #[test]
+    fn conversation() {
+        let mut rng = thread_rng();
+        let (mut alice, mut bob) = pair();
+
+        let mut previous_dh_public_key = None;
+
+        // The responder can talk first, before it has received anything
+        for round in 0..3 {
+            let (header, ciphertext) = bob.encrypt(format!("bob {}", round).as_bytes(), b"ad").unwrap();
+            assert_eq!(alice.decrypt(&mut rng, &header, &ciphertext, b"ad").unwrap(), format!("bob {}", round).into_bytes());
+
+            let (header, ciphertext) = alice.encrypt(format!("alice {}", round).as_bytes(), b"ad").unwrap();
+            assert_eq!(bob.decrypt(&mut rng, &header, &ciphertext, b"ad").unwrap(), format!("alice {}", round).into_bytes());
+
+            // Every round trip moves the DH ratchet forward
+            assert_ne!(previous_dh_public_key, Some(header.dh_public_key));
+            previous_dh_public_key = Some(header.dh_public_key);
+        }
+    }
+
+    #[test]
+    fn out_of_order_messages() {
+        let mut rng = thread_rng();
+        let (mut alice, mut bob) = pair();
+
+        let messages = (0..5).map(|index| alice.encrypt(&[index], b"").unwrap()).collect::<Vec<_>>();
+
+        for index in &[3usize, 0, 4, 2, 1] {
+            let (header, ciphertext) = &messages[*index];
+            assert_eq!(bob.decrypt(&mut rng, header, ciphertext, b"").unwrap(), vec![*index as u8]);
+        }
+        assert_eq!(bob.skipped_message_keys(), 0);
+
+        // Messages from a previous sending chain are still readable after a DH ratchet step
+        let late = alice.encrypt(b"late", b"").unwrap();
+        let (header, ciphertext) = bob.encrypt(b"reply", b"").unwrap();
+        alice.decrypt(&mut rng, &header, &ciphertext, b"").unwrap();
+        let (header, ciphertext) = alice.encrypt(b"new chain", b"").unwrap();
+
+        assert_eq!(bob.decrypt(&mut rng, &header, &ciphertext, b"").unwrap(), b"new chain".to_vec());
+        assert_eq!(bob.decrypt(&mut rng, &late.0, &late.1, b"").unwrap(), b"late".to_vec());
+    }
+
+    #[test]
+    fn tampered_and_replayed_messages_are_rejected() {
+        let mut rng = thread_rng();
+        let (mut alice, mut bob) = pair();
+
+        let (header, ciphertext) = alice.encrypt(b"hello", b"ad").unwrap();
+
+        let mut tampered = ciphertext.clone();
+        tampered[0] ^= 1;
+        assert_eq!(bob.decrypt(&mut rng, &header, &tampered, b"ad"), Err(RatchetError::Decryption));
+        assert_eq!(bob.decrypt(&mut rng, &header, &ciphertext, b"other ad"), Err(RatchetError::Decryption));
+
+        assert_eq!(bob.decrypt(&mut rng, &header, &ciphertext, b"ad").unwrap(), b"hello".to_vec());
+        assert_eq!(bob.decrypt(&mut rng, &header, &ciphertext, b"ad"), Err(RatchetError::Decryption));
+    }
+
+    #[test]
+    fn skipping_too_far_ahead_is_rejected() {
+        let mut rng = thread_rng();
+        let (mut alice, mut bob) = pair();
+
+        let (mut header, ciphertext) = alice.encrypt(b"hello", b"").unwrap();
+        header.message_number = crate::ratchet::double_ratchet::MAX_SKIP + 1;
+
+        assert_eq!(bob.decrypt(&mut rng, &header, &ciphertext, b""), Err(RatchetError::TooManySkippedMessages));
+    }
+}
@@ -0,0 +1,33 @@
+use std::fmt::{ Formatter, Display, Error };
+use crate::x25519::CryptoError;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RatchetError {
+    Crypto(CryptoError),
+    KeyDerivation,
+    Encryption,
+    Decryption,
+    NoSendingChain,
+    TooManySkippedMessages,
+}
+
+impl Display for RatchetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            RatchetError::Crypto(error) => write!(f, "{}", error),
+            RatchetError::KeyDerivation => f.write_str("failed to derive ratchet keys"),
+            RatchetError::Encryption => f.write_str("failed to encrypt message"),
+            RatchetError::Decryption => f.write_str("failed to decrypt message"),
+            RatchetError::NoSendingChain => f.write_str("no sending chain established yet"),
+            RatchetError::TooManySkippedMessages => f.write_str("too many skipped messages"),
+        }
+    }
+}
+
+impl std::error::Error for RatchetError {}
+
+impl From<CryptoError> for RatchetError {
+    fn from(error: CryptoError) -> Self {
+        RatchetError::Crypto(error)
+    }
+}
@@ -4,6 +4,10 @@ use rand::{
     prelude::ThreadRng,
     RngCore,
 };
+use openssl::error::ErrorStack;
+use crate::hkdf::{ self, HASH_SIZE };
+
+const PACKET_MAC_LABEL: &[u8] = b"chat-test packet mac";
 
 #[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
 pub struct SharedMacSecret([u8; 32]);
@@ -15,6 +19,14 @@ impl SharedMacSecret {
 
         Self (slice)
     }
+
+    // The secret also serves as the Noise PSK, so packets are authenticated with a key derived from it
+    pub fn mac(&self, parts: &[&[u8]]) -> Result<[u8; HASH_SIZE], ErrorStack> {
+        let mut key = [0u8; HASH_SIZE];
+        hkdf::expand(&self.0, PACKET_MAC_LABEL, &mut key)?;
+
+        hkdf::hmac(&key, parts)
+    }
 }
 
 impl AsRef<[u8]> for SharedMacSecret {
@@ -0,0 +1,35 @@
+use std::fmt::{ Formatter, Display, Error };
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CryptoError {
+    InvalidKeyLength {
+        expected: usize,
+        actual: usize,
+    },
+    KeyCreation,
+    ContextCreation,
+    KeyExtraction,
+    Derivation,
+    Signing,
+    InvalidSignature,
+    Mac,
+    Encryption,
+}
+
+impl Display for CryptoError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            CryptoError::InvalidKeyLength { expected, actual } => write!(f, "invalid key length, expected at least {} bytes, got {}", expected, actual),
+            CryptoError::KeyCreation => f.write_str("failed to create key"),
+            CryptoError::ContextCreation => f.write_str("failed to create context"),
+            CryptoError::KeyExtraction => f.write_str("failed to extract raw key"),
+            CryptoError::Derivation => f.write_str("failed to derive shared key"),
+            CryptoError::Signing => f.write_str("failed to sign message"),
+            CryptoError::InvalidSignature => f.write_str("invalid signature"),
+            CryptoError::Mac => f.write_str("failed to compute MAC"),
+            CryptoError::Encryption => f.write_str("failed to encrypt"),
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
@@ -1,10 +1,11 @@
-#[allow(unused_imports)]
-#[allow(unused_variables)]
-#[allow(dead_code)]
+#![allow(non_camel_case_types)]
+#![allow(clippy::upper_case_acronyms)]
 
+use std::os::raw::{ c_int, c_uchar };
+use crate::x25519::CryptoError;
 
-const EVP_PKEY_X25519: i32 = 1034;
-const EVP_PKEY_ED25519: i32 = 1087;
+const EVP_PKEY_X25519: c_int = 1034;
+const EVP_PKEY_ED25519: c_int = 1087;
 
 #[repr(C)]
 struct ENGINE { _unused: [u8; 0] }
@@ -23,87 +24,203 @@ pub const PRIVATE_KEY_SIZE: usize = 64;
 pub const SIGNATURE_SIZE: usize = 64;
 
 extern "C" {
-    fn EVP_DigestSign(ctx: *mut EVP_MD_CTX, sigret: *mut ::std::os::raw::c_uchar, siglen: *mut usize, tbs: *const ::std::os::raw::c_uchar, tbslen: usize) -> ::std::os::raw::c_int;
-    fn EVP_DigestSignInit(ctx: *mut EVP_MD_CTX, pctx: *mut *mut EVP_PKEY_CTX, type_: *const EVP_MD, e: *mut ENGINE, pkey: *mut EVP_PKEY) -> ::std::os::raw::c_int;
+    fn EVP_DigestSign(ctx: *mut EVP_MD_CTX, sigret: *mut c_uchar, siglen: *mut usize, tbs: *const c_uchar, tbslen: usize) -> c_int;
+    fn EVP_DigestSignInit(ctx: *mut EVP_MD_CTX, pctx: *mut *mut EVP_PKEY_CTX, type_: *const EVP_MD, e: *mut ENGINE, pkey: *mut EVP_PKEY) -> c_int;
+    fn EVP_DigestVerify(ctx: *mut EVP_MD_CTX, sigret: *const c_uchar, siglen: usize, tbs: *const c_uchar, tbslen: usize) -> c_int;
+    fn EVP_DigestVerifyInit(ctx: *mut EVP_MD_CTX, pctx: *mut *mut EVP_PKEY_CTX, type_: *const EVP_MD, e: *mut ENGINE, pkey: *mut EVP_PKEY) -> c_int;
     fn EVP_MD_CTX_new() -> *mut EVP_MD_CTX;
+    fn EVP_MD_CTX_free(ctx: *mut EVP_MD_CTX);
     fn EVP_PKEY_CTX_new(pkey: *mut EVP_PKEY, e: *mut ENGINE) -> *mut EVP_PKEY_CTX;
-    fn EVP_PKEY_derive_init(ctx: *mut EVP_PKEY_CTX) -> ::std::os::raw::c_int;
-    fn EVP_PKEY_derive_set_peer(ctx: *mut EVP_PKEY_CTX, peer: *mut EVP_PKEY) -> ::std::os::raw::c_int;
-    fn EVP_PKEY_derive(ctx: *mut EVP_PKEY_CTX, key: *mut ::std::os::raw::c_uchar, keylen: *mut usize) -> ::std::os::raw::c_int;
-    fn EVP_PKEY_get_raw_private_key(pkey: *const EVP_PKEY, priv_: *mut ::std::os::raw::c_uchar, len: *mut usize) -> ::std::os::raw::c_int;
-    fn EVP_PKEY_get_raw_public_key(pkey: *const EVP_PKEY, pub_: *mut ::std::os::raw::c_uchar, len: *mut usize) -> ::std::os::raw::c_int;
-    fn EVP_PKEY_new_raw_private_key(type_: ::std::os::raw::c_int, e: *mut ENGINE, priv_: *const ::std::os::raw::c_uchar, len: usize) -> *mut EVP_PKEY;
-    fn EVP_PKEY_new_raw_public_key(type_: ::std::os::raw::c_int, e: *mut ENGINE, pub_: *const ::std::os::raw::c_uchar, len: usize) -> *mut EVP_PKEY;
+    fn EVP_PKEY_CTX_free(ctx: *mut EVP_PKEY_CTX);
+    fn EVP_PKEY_derive_init(ctx: *mut EVP_PKEY_CTX) -> c_int;
+    fn EVP_PKEY_derive_set_peer(ctx: *mut EVP_PKEY_CTX, peer: *mut EVP_PKEY) -> c_int;
+    fn EVP_PKEY_derive(ctx: *mut EVP_PKEY_CTX, key: *mut c_uchar, keylen: *mut usize) -> c_int;
+    fn EVP_PKEY_get_raw_private_key(pkey: *const EVP_PKEY, priv_: *mut c_uchar, len: *mut usize) -> c_int;
+    fn EVP_PKEY_get_raw_public_key(pkey: *const EVP_PKEY, pub_: *mut c_uchar, len: *mut usize) -> c_int;
+    fn EVP_PKEY_new_raw_private_key(type_: c_int, e: *mut ENGINE, priv_: *const c_uchar, len: usize) -> *mut EVP_PKEY;
+    fn EVP_PKEY_new_raw_public_key(type_: c_int, e: *mut ENGINE, pub_: *const c_uchar, len: usize) -> *mut EVP_PKEY;
+    fn EVP_PKEY_free(pkey: *mut EVP_PKEY);
 }
 
-pub fn create_key_pair(secret: &[u8]) -> ([u8; PRIVATE_KEY_SIZE], [u8; PUBLIC_KEY_SIZE]) {
-    let key = unsafe { EVP_PKEY_new_raw_private_key(EVP_PKEY_ED25519, std::ptr::null_mut(), secret.as_ptr() as *mut u8, SECRET_KEY_SIZE) };
+struct Pkey(*mut EVP_PKEY);
 
-    let mut size = PRIVATE_KEY_SIZE;
-    let mut private = [0u8; PRIVATE_KEY_SIZE];
+impl Pkey {
+    fn private(type_: c_int, secret: &[u8]) -> Result<Self, CryptoError> {
+        check_length(secret, SECRET_KEY_SIZE)?;
 
-    unsafe { EVP_PKEY_get_raw_private_key(key, private.as_mut_ptr(), &mut size); }
-    assert_eq!(size, SECRET_KEY_SIZE);
+        let key = unsafe { EVP_PKEY_new_raw_private_key(type_, std::ptr::null_mut(), secret.as_ptr(), SECRET_KEY_SIZE) };
+        if key.is_null() {
+            return Err(CryptoError::KeyCreation);
+        }
 
-    unsafe { EVP_PKEY_get_raw_public_key(key, private.as_mut_ptr().offset(SECRET_KEY_SIZE as isize), &mut size); }
-    assert_eq!(size, PUBLIC_KEY_SIZE);
+        Ok(Pkey(key))
+    }
 
-    let mut size = PUBLIC_KEY_SIZE;
-    let mut public = [0u8; PUBLIC_KEY_SIZE];
+    fn public(type_: c_int, public: &[u8]) -> Result<Self, CryptoError> {
+        check_length(public, PUBLIC_KEY_SIZE)?;
+
+        let key = unsafe { EVP_PKEY_new_raw_public_key(type_, std::ptr::null_mut(), public.as_ptr(), PUBLIC_KEY_SIZE) };
+        if key.is_null() {
+            return Err(CryptoError::KeyCreation);
+        }
+
+        Ok(Pkey(key))
+    }
+
+    fn raw_private_key(&self, out: &mut [u8]) -> Result<(), CryptoError> {
+        let mut size = out.len();
+        let status = unsafe { EVP_PKEY_get_raw_private_key(self.0, out.as_mut_ptr(), &mut size) };
+        if status != 1 || size != out.len() {
+            return Err(CryptoError::KeyExtraction);
+        }
+
+        Ok(())
+    }
+
+    fn raw_public_key(&self, out: &mut [u8]) -> Result<(), CryptoError> {
+        let mut size = out.len();
+        let status = unsafe { EVP_PKEY_get_raw_public_key(self.0, out.as_mut_ptr(), &mut size) };
+        if status != 1 || size != out.len() {
+            return Err(CryptoError::KeyExtraction);
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Pkey {
+    fn drop(&mut self) {
+        unsafe { EVP_PKEY_free(self.0) }
+    }
+}
+
+struct PkeyCtx(*mut EVP_PKEY_CTX);
+
+impl PkeyCtx {
+    fn new(key: &Pkey) -> Result<Self, CryptoError> {
+        let ctx = unsafe { EVP_PKEY_CTX_new(key.0, std::ptr::null_mut()) };
+        if ctx.is_null() {
+            return Err(CryptoError::ContextCreation);
+        }
+
+        Ok(PkeyCtx(ctx))
+    }
+}
+
+impl Drop for PkeyCtx {
+    fn drop(&mut self) {
+        unsafe { EVP_PKEY_CTX_free(self.0) }
+    }
+}
+
+struct MdCtx(*mut EVP_MD_CTX);
 
-    unsafe { EVP_PKEY_get_raw_public_key(key, public.as_mut_ptr(), &mut size); }
-    assert_eq!(size, PUBLIC_KEY_SIZE);
+impl MdCtx {
+    fn new() -> Result<Self, CryptoError> {
+        let ctx = unsafe { EVP_MD_CTX_new() };
+        if ctx.is_null() {
+            return Err(CryptoError::ContextCreation);
+        }
 
-    return (private, public);
+        Ok(MdCtx(ctx))
+    }
 }
 
-pub fn calculate_public_key(secret: &[u8]) -> [u8; PUBLIC_KEY_SIZE] {
-    let key = unsafe { EVP_PKEY_new_raw_private_key(EVP_PKEY_X25519, std::ptr::null_mut(), secret.as_ptr() as *mut u8, SECRET_KEY_SIZE) };
+impl Drop for MdCtx {
+    fn drop(&mut self) {
+        unsafe { EVP_MD_CTX_free(self.0) }
+    }
+}
+
+fn check_length(bytes: &[u8], expected: usize) -> Result<(), CryptoError> {
+    if bytes.len() < expected {
+        return Err(CryptoError::InvalidKeyLength { expected, actual: bytes.len() });
+    }
+
+    Ok(())
+}
+
+pub fn create_key_pair(secret: &[u8]) -> Result<([u8; PRIVATE_KEY_SIZE], [u8; PUBLIC_KEY_SIZE]), CryptoError> {
+    let key = Pkey::private(EVP_PKEY_ED25519, secret)?;
+
+    let mut private = [0u8; PRIVATE_KEY_SIZE];
+    key.raw_private_key(&mut private[..SECRET_KEY_SIZE])?;
+    key.raw_public_key(&mut private[SECRET_KEY_SIZE..])?;
 
-    let mut size = PUBLIC_KEY_SIZE;
     let mut public = [0u8; PUBLIC_KEY_SIZE];
+    key.raw_public_key(&mut public)?;
+
+    Ok((private, public))
+}
 
-    let status = unsafe { EVP_PKEY_get_raw_public_key(key, public.as_mut_ptr(), &mut size) };
-    assert_eq!(status, 1);
-    assert_eq!(size, PUBLIC_KEY_SIZE);
+pub fn calculate_public_key(secret: &[u8]) -> Result<[u8; PUBLIC_KEY_SIZE], CryptoError> {
+    let key = Pkey::private(EVP_PKEY_X25519, secret)?;
 
-    public
+    let mut public = [0u8; PUBLIC_KEY_SIZE];
+    key.raw_public_key(&mut public)?;
+
+    Ok(public)
 }
 
-pub fn create_shared_key(peer_public: &[u8], secret: &[u8]) -> [u8; SECRET_KEY_SIZE] {
-    let key = unsafe { EVP_PKEY_new_raw_private_key(EVP_PKEY_X25519, std::ptr::null_mut(), secret.as_ptr() as *mut u8, SECRET_KEY_SIZE) };
-    let peer_key = unsafe { EVP_PKEY_new_raw_public_key(EVP_PKEY_X25519, std::ptr::null_mut(), peer_public.as_ptr() as *mut u8, PUBLIC_KEY_SIZE) };
+pub fn create_shared_key(peer_public: &[u8], secret: &[u8]) -> Result<[u8; SECRET_KEY_SIZE], CryptoError> {
+    let key = Pkey::private(EVP_PKEY_X25519, secret)?;
+    let peer_key = Pkey::public(EVP_PKEY_X25519, peer_public)?;
 
-    let ctx = unsafe { EVP_PKEY_CTX_new(key, std::ptr::null_mut()) };
+    let ctx = PkeyCtx::new(&key)?;
 
-    let status = unsafe { EVP_PKEY_derive_init(ctx) };
-    assert_eq!(status, 1);
+    if unsafe { EVP_PKEY_derive_init(ctx.0) } != 1 {
+        return Err(CryptoError::Derivation);
+    }
 
-    let status = unsafe { EVP_PKEY_derive_set_peer(ctx, peer_key) };
-    assert_eq!(status, 1);
+    if unsafe { EVP_PKEY_derive_set_peer(ctx.0, peer_key.0) } != 1 {
+        return Err(CryptoError::Derivation);
+    }
 
     let mut size = SECRET_KEY_SIZE;
     let mut result = [0u8; SECRET_KEY_SIZE];
-    let status = unsafe { EVP_PKEY_derive(ctx, result.as_mut_ptr(), &mut size) };
-    assert_eq!(status, 1);
-    assert_eq!(size, SECRET_KEY_SIZE);
+    let status = unsafe { EVP_PKEY_derive(ctx.0, result.as_mut_ptr(), &mut size) };
+    if status != 1 || size != SECRET_KEY_SIZE {
+        return Err(CryptoError::Derivation);
+    }
 
-    result
+    Ok(result)
 }
 
-pub fn sign_message(private_key: &[u8], message: &[u8]) -> [u8; SIGNATURE_SIZE] {
-    let ctx = unsafe { EVP_MD_CTX_new() };
-    let key = unsafe { EVP_PKEY_new_raw_private_key(EVP_PKEY_ED25519, std::ptr::null_mut(), private_key.as_ptr() as *mut u8, SECRET_KEY_SIZE) };
+pub fn sign_message(private_key: &[u8], message: &[u8]) -> Result<[u8; SIGNATURE_SIZE], CryptoError> {
+    let key = Pkey::private(EVP_PKEY_ED25519, private_key)?;
+    let ctx = MdCtx::new()?;
 
-    let status = unsafe { EVP_DigestSignInit(ctx, std::ptr::null_mut(), std::ptr::null(), std::ptr::null_mut(), key) };
-    assert_eq!(status, 1);
+    if unsafe { EVP_DigestSignInit(ctx.0, std::ptr::null_mut(), std::ptr::null(), std::ptr::null_mut(), key.0) } != 1 {
+        return Err(CryptoError::Signing);
+    }
 
     let mut signature = [0u8; SIGNATURE_SIZE];
     let mut signature_length = SIGNATURE_SIZE;
 
-    let status = unsafe { EVP_DigestSign(ctx, signature.as_mut_ptr(), &mut signature_length, message.as_ptr(), message.len()) };
-    assert_eq!(status, 1);
-    assert_eq!(signature_length, SIGNATURE_SIZE);
+    let status = unsafe { EVP_DigestSign(ctx.0, signature.as_mut_ptr(), &mut signature_length, message.as_ptr(), message.len()) };
+    if status != 1 || signature_length != SIGNATURE_SIZE {
+        return Err(CryptoError::Signing);
+    }
+
+    Ok(signature)
+}
+
+pub fn verify_signature(public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), CryptoError> {
+    if signature.len() != SIGNATURE_SIZE {
+        return Err(CryptoError::InvalidSignature);
+    }
 
-    signature
-}
\ No newline at end of file
+    let key = Pkey::public(EVP_PKEY_ED25519, public_key)?;
+    let ctx = MdCtx::new()?;
+
+    if unsafe { EVP_DigestVerifyInit(ctx.0, std::ptr::null_mut(), std::ptr::null(), std::ptr::null_mut(), key.0) } != 1 {
+        return Err(CryptoError::ContextCreation);
+    }
+
+    let status = unsafe { EVP_DigestVerify(ctx.0, signature.as_ptr(), signature.len(), message.as_ptr(), message.len()) };
+    if status != 1 {
+        return Err(CryptoError::InvalidSignature);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,50 @@
+use std::fmt::{ Formatter, Debug, Error };
+use rand::{
+    prelude::ThreadRng,
+    RngCore,
+};
+use crate::x25519::{
+    PublicKey,
+    SharedKey,
+    CryptoError,
+    curve25519::SECRET_KEY_SIZE,
+};
+
+pub struct EphemeralSecret ([u8; SECRET_KEY_SIZE]);
+
+impl EphemeralSecret {
+    pub fn new(rng: &mut ThreadRng) -> Self {
+        let mut slice = [0u8; SECRET_KEY_SIZE];
+        rng.fill_bytes(&mut slice);
+
+        EphemeralSecret(slice)
+    }
+
+    pub fn public_key(&self) -> Result<PublicKey, CryptoError> {
+        PublicKey::new(&self.0)
+    }
+
+    pub fn diffie_hellman(&self, public_key: &PublicKey) -> Result<SharedKey, CryptoError> {
+        Ok(SharedKey::from(crate::x25519::curve25519::create_shared_key(public_key.as_ref(), &self.0)?.to_vec()))
+    }
+}
+
+impl From<[u8; SECRET_KEY_SIZE]> for EphemeralSecret {
+    fn from(slice: [u8; SECRET_KEY_SIZE]) -> Self {
+        EphemeralSecret(slice)
+    }
+}
+
+impl Drop for EphemeralSecret {
+    fn drop(&mut self) {
+        for byte in self.0.iter_mut() {
+            unsafe { std::ptr::write_volatile(byte, 0) };
+        }
+    }
+}
+
+impl Debug for EphemeralSecret {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.write_str("\"<ephemeral secret>\"")
+    }
+}
@@ -4,6 +4,14 @@ mod private_key;
 pub use private_key::PrivateKey;
 mod shared_key;
 pub use shared_key::SharedKey;
+mod crypto_error;
+pub use crypto_error::CryptoError;
+mod signature;
+pub use signature::Signature;
+mod verifying_key;
+pub use verifying_key::VerifyingKey;
+mod ephemeral_secret;
+pub use ephemeral_secret::EphemeralSecret;
 
 #[allow(unused_imports)]
 #[allow(unused_variables)]
@@ -12,7 +20,7 @@ pub(super) mod curve25519;
 
 #[cfg(test)]
 mod tests {
-    use crate::x25519::{PrivateKey, PublicKey, SharedKey};
+    use crate::x25519::{PrivateKey, PublicKey, SharedKey, CryptoError, VerifyingKey, EphemeralSecret};
     use rand::RngCore;
 
     #[test]
@@ -22,7 +30,7 @@ mod tests {
 
         rng.fill_bytes(&mut secret);
 
-        let private_key = PrivateKey::new(&secret);
+        let private_key = PrivateKey::new(&secret).unwrap();
 
         println!("Generated private key: {}", private_key);
     }
@@ -34,7 +42,7 @@ mod tests {
 
         rng.fill_bytes(&mut secret);
 
-        let public_key = PublicKey::new(&secret);
+        let public_key = PublicKey::new(&secret).unwrap();
 
         println!("Generated public key: {}", public_key);
     }
@@ -46,11 +54,67 @@ mod tests {
 
         rng.fill_bytes(&mut secret);
 
-        let private_key = PrivateKey::new(&secret);
-        let public_key = PublicKey::new(&secret);
+        let private_key = PrivateKey::new(&secret).unwrap();
+        let public_key = PublicKey::new(&secret).unwrap();
 
-        let shared_key = SharedKey::derive(&private_key, &public_key);
+        let shared_key = SharedKey::derive(&private_key, &public_key).unwrap();
 
         println!("Generated shared key: {}", shared_key);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn short_secret_is_rejected() {
+        let secret = [0u8; 16];
+
+        assert_eq!(PrivateKey::new(&secret), Err(CryptoError::InvalidKeyLength { expected: 32, actual: 16 }));
+        assert_eq!(PublicKey::new(&secret), Err(CryptoError::InvalidKeyLength { expected: 32, actual: 16 }));
+    }
+
+    #[test]
+    fn low_order_peer_key_is_rejected() {
+        let mut secret = [0u8; 128];
+        let mut rng = rand::thread_rng();
+
+        rng.fill_bytes(&mut secret);
+
+        let private_key = PrivateKey::new(&secret).unwrap();
+        let public_key = PublicKey::from(vec![0u8; 32]);
+
+        assert_eq!(SharedKey::derive(&private_key, &public_key), Err(CryptoError::Derivation));
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let mut secret = [0u8; 128];
+        let mut rng = rand::thread_rng();
+
+        rng.fill_bytes(&mut secret);
+
+        let private_key = PrivateKey::new(&secret).unwrap();
+        let verifying_key = VerifyingKey::new(&private_key);
+
+        let signature = private_key.sign(b"handshake transcript").unwrap();
+
+        assert_eq!(verifying_key.verify(b"handshake transcript", &signature), Ok(()));
+        assert_eq!(verifying_key.verify(b"forged transcript", &signature), Err(CryptoError::InvalidSignature));
+
+        rng.fill_bytes(&mut secret);
+
+        let other_verifying_key = VerifyingKey::new(&PrivateKey::new(&secret).unwrap());
+
+        assert_eq!(other_verifying_key.verify(b"handshake transcript", &signature), Err(CryptoError::InvalidSignature));
+    }
+
+    #[test]
+    fn ephemeral_diffie_hellman() {
+        let mut rng = rand::thread_rng();
+
+        let local = EphemeralSecret::new(&mut rng);
+        let remote = EphemeralSecret::new(&mut rng);
+
+        let local_shared_key = local.diffie_hellman(&remote.public_key().unwrap()).unwrap();
+        let remote_shared_key = remote.diffie_hellman(&local.public_key().unwrap()).unwrap();
+
+        assert_eq!(local_shared_key, remote_shared_key);
+    }
+}
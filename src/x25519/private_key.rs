@@ -1,5 +1,6 @@
 use std::fmt::{ Formatter, Display, Debug, Error };
 use serde::{ Serialize, Deserialize };
+use crate::x25519::{ CryptoError, Signature };
 
 big_array! { BigArray; }
 
@@ -7,8 +8,12 @@ big_array! { BigArray; }
 pub struct PrivateKey (#[serde(with = "BigArray")] [u8; 64]);
 
 impl PrivateKey {
-    pub fn new(secret: &[u8]) -> Self {
-        PrivateKey(crate::x25519::curve25519::create_key_pair(secret).0)
+    pub fn new(secret: &[u8]) -> Result<Self, CryptoError> {
+        Ok(PrivateKey(crate::x25519::curve25519::create_key_pair(secret)?.0))
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Result<Signature, CryptoError> {
+        Ok(Signature::from(crate::x25519::curve25519::sign_message(&self.0, message)?))
     }
 }
 
@@ -53,10 +58,6 @@ impl PartialEq for PrivateKey {
     fn eq(&self, other: &Self) -> bool {
         self.0.as_ref() == other.0.as_ref()
     }
-
-    fn ne(&self, other: &Self) -> bool {
-        self.0.as_ref() != other.0.as_ref()
-    }
 }
 
 impl Eq for PrivateKey {}
\ No newline at end of file
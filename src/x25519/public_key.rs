@@ -1,12 +1,13 @@
 use std::fmt::{ Formatter, Display, Debug, Error };
 use serde::{ Serialize, Deserialize };
+use crate::x25519::CryptoError;
 
-#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
 pub struct PublicKey ([u8; 32]);
 
 impl PublicKey {
-    pub fn new(secret: &[u8]) -> Self {
-        PublicKey(crate::x25519::curve25519::calculate_public_key(secret.as_ref()))
+    pub fn new(secret: &[u8]) -> Result<Self, CryptoError> {
+        Ok(PublicKey(crate::x25519::curve25519::calculate_public_key(secret)?))
     }
 }
 
@@ -1,13 +1,13 @@
 use std::fmt::{ Formatter, Display, Debug, Error };
-use crate::x25519::{PublicKey, PrivateKey};
+use crate::x25519::{ PublicKey, PrivateKey, CryptoError };
 use serde::{ Serialize, Deserialize };
 
 #[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
 pub struct SharedKey ([u8; 32]);
 
 impl SharedKey {
-    pub fn derive(private_key: &PrivateKey, public_key: &PublicKey) -> SharedKey {
-        SharedKey(crate::x25519::curve25519::create_shared_key(public_key.as_ref(), private_key.as_ref()))
+    pub fn derive(private_key: &PrivateKey, public_key: &PublicKey) -> Result<SharedKey, CryptoError> {
+        Ok(SharedKey(crate::x25519::curve25519::create_shared_key(public_key.as_ref(), private_key.as_ref())?))
     }
 }
 
@@ -0,0 +1,60 @@
+use std::fmt::{ Formatter, Display, Debug, Error };
+use serde::{ Serialize, Deserialize };
+use crate::x25519::{
+    curve25519::SIGNATURE_SIZE,
+    private_key::BigArray,
+};
+
+#[derive(Serialize, Deserialize, Copy, Clone)]
+pub struct Signature (#[serde(with = "BigArray")] [u8; SIGNATURE_SIZE]);
+
+impl AsRef<[u8]> for Signature {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsMut<[u8]> for Signature {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+impl Debug for Signature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.write_str(format!("\"{}\"", base64::encode(&self.0 as &[u8])).as_str()).unwrap();
+
+        Ok(())
+    }
+}
+
+impl Display for Signature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.write_str(format!("\"{}\"", base64::encode(&self.0 as &[u8])).as_str()).unwrap();
+
+        Ok(())
+    }
+}
+
+impl From<[u8; SIGNATURE_SIZE]> for Signature {
+    fn from(slice: [u8; SIGNATURE_SIZE]) -> Self {
+        Signature(slice)
+    }
+}
+
+impl From<Vec<u8>> for Signature {
+    fn from(vec: Vec<u8>) -> Self {
+        let mut slice = [0u8; SIGNATURE_SIZE];
+        slice.copy_from_slice(vec.as_slice());
+
+        Signature(slice)
+    }
+}
+
+impl PartialEq for Signature {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_ref() == other.0.as_ref()
+    }
+}
+
+impl Eq for Signature {}
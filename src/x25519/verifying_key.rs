@@ -0,0 +1,61 @@
+use std::fmt::{ Formatter, Display, Debug, Error };
+use serde::{ Serialize, Deserialize };
+use crate::x25519::{
+    PrivateKey,
+    Signature,
+    CryptoError,
+    curve25519::{ PUBLIC_KEY_SIZE, SECRET_KEY_SIZE },
+};
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+pub struct VerifyingKey ([u8; PUBLIC_KEY_SIZE]);
+
+impl VerifyingKey {
+    pub fn new(private_key: &PrivateKey) -> Self {
+        let mut slice = [0u8; PUBLIC_KEY_SIZE];
+        slice.copy_from_slice(&private_key.as_ref()[SECRET_KEY_SIZE..]);
+
+        VerifyingKey(slice)
+    }
+
+    pub fn verify(&self, message: &[u8], signature: &Signature) -> Result<(), CryptoError> {
+        crate::x25519::curve25519::verify_signature(&self.0, message, signature.as_ref())
+    }
+}
+
+impl AsRef<[u8]> for VerifyingKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsMut<[u8]> for VerifyingKey {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+impl Debug for VerifyingKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.write_str(format!("\"{}\"", base64::encode(&self.0)).as_str()).unwrap();
+
+        Ok(())
+    }
+}
+
+impl Display for VerifyingKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.write_str(format!("\"{}\"", base64::encode(&self.0)).as_str()).unwrap();
+
+        Ok(())
+    }
+}
+
+impl From<Vec<u8>> for VerifyingKey {
+    fn from(vec: Vec<u8>) -> Self {
+        let mut slice = [0u8; PUBLIC_KEY_SIZE];
+        slice.copy_from_slice(vec.as_slice());
+
+        VerifyingKey(slice)
+    }
+}
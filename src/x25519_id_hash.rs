@@ -1,11 +1,19 @@
-use std::fmt::{ Formatter, Display, Debug, Error };
+use std::{
+    fmt::{ Formatter, Display, Debug, Error },
+    time::{ Duration, SystemTime, UNIX_EPOCH },
+};
 use serde::{ Serialize, Deserialize };
 use crate::{
     SharedMacSecret,
+    hkdf::{ self, HASH_SIZE },
     x25519::PublicKey
 };
 
-#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Copy, Clone)]
+// How long an identifier is sent on the wire before it is replaced by the next one
+pub const EPOCH_LENGTH: Duration = Duration::from_secs(300);
+const ROUTING_ID_LABEL: &[u8] = b"chat-test routing id";
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone)]
 pub struct x25519IDHash ([u8; 32]);
 
 impl x25519IDHash {
@@ -16,6 +24,32 @@ impl x25519IDHash {
         sha256.update(shared_mac_secret.as_ref());
         Self (sha256.finish())
     }
+
+    pub fn first_contact(shared_mac_secret: SharedMacSecret) -> Self {
+        let mut sha256 = openssl::sha::Sha256::new();
+
+        sha256.update(b"first contact");
+        sha256.update(shared_mac_secret.as_ref());
+        Self (sha256.finish())
+    }
+
+    // Both peers agree on the epoch as long as their clocks are less than an epoch apart
+    pub fn epoch(time: SystemTime) -> u64 {
+        time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / EPOCH_LENGTH.as_secs()
+    }
+
+    pub fn current_epoch() -> u64 {
+        x25519IDHash::epoch(SystemTime::now())
+    }
+
+    // Identifier sent on the wire in place of this one during `epoch`, without the shared secret
+    // the identifiers of different epochs cannot be linked to each other
+    pub fn routing_id(&self, shared_mac_secret: &SharedMacSecret, epoch: u64) -> Self {
+        let mut key = [0u8; HASH_SIZE];
+        hkdf::expand(shared_mac_secret.as_ref(), ROUTING_ID_LABEL, &mut key).unwrap();
+
+        Self (hkdf::hmac(&key, &[&self.0, &epoch.to_be_bytes()]).unwrap())
+    }
 }
 
 impl AsRef<[u8]> for x25519IDHash {